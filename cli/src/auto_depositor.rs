@@ -1,40 +1,272 @@
 use anchor_lang::AnchorDeserialize;
 use anyhow::{anyhow, Context, Result};
-use config::Configuration;
+use config::{swap_back::SwapBackPolicy, Configuration};
 use jupiter_api::swapper::Swapper;
-use perpetuals::jlp_cacher::LP_TOKEN_MINT;
+use perpetuals::event_parser::PerpetualsEvent;
+use perpetuals::jlp_cacher::{
+    apply_slippage, token_to_usd_amount, unwrap_sol_ix, usd_to_token_amount, wrap_sol_ixs,
+    JLPCacheAccountKeys, LP_TOKEN_MINT, USD_DECIMALS,
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use jupiter_api::swap_types::compile_v0_message;
+use solana_transaction_status::EncodedTransactionWithStatusMeta;
 use solana_sdk::{
-    compute_budget::ComputeBudgetInstruction, program_pack::Pack, signer::Signer,
-    transaction::Transaction,
+    account::Account, commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction,
+    message::VersionedMessage, program_pack::Pack, signature::Signature, signer::Signer,
+    transaction::{Transaction, VersionedTransaction},
 };
 use solana_sdk::{pubkey::Pubkey, signature::Keypair};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::signal::unix::{Signal, SignalKind};
+use tokio::sync::watch;
+use tx_sender::{
+    compute_units::ComputeUnitEstimator,
+    confirmation::{ConfirmationTracker, TxOutcome},
+    nonce::{nonce_address, DurableNonce},
+    priority_fee::PriorityFeeEstimator,
+    sender::{Sent, TransactionSender},
+    submitter::Submitter,
+};
 
+use crate::budget::{BudgetStatus, DepositBudget};
 use crate::check_jlp_liquidity::{PERPETUALS_ACCT, POOL_ACCT};
+use crate::ledger::{Ledger, LedgerEvent};
+use crate::capacity::CapacityWatcher;
+use crate::pool_watcher::{PoolUpdate, PoolWatcher};
+use crate::pre_swap::PreSwapper;
+use crate::swap_back::{SwapBack, SwapBackReport};
+
+/// sent to the swap task once a deposit has landed at the tracked commitment
+#[derive(Debug, Clone)]
+pub struct DepositLanded {
+    pub signature: Signature,
+    /// jlp minted to the owner by the deposit
+    pub lp_amount: u64,
+}
+
+/// how the configured wallets share the pool's capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletMode {
+    /// every wallet deposits as soon as there is room, each sized against an equal share of it
+    Split,
+    /// wallets take turns depositing, each sized against all of the room
+    RoundRobin,
+}
 
-const LP_MINT_STR: &str = "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4";
-const USDC_MINT_STR: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+impl FromStr for WalletMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "split" => Ok(Self::Split),
+            "round-robin" => Ok(Self::RoundRobin),
+            _ => Err(anyhow!("invalid wallet mode {s}, expected split or round-robin")),
+        }
+    }
+}
+
+/// hands the deposit turn to each wallet in order, skipping wallets that stopped depositing
+struct Turns {
+    current: AtomicUsize,
+    stopped: Vec<AtomicBool>,
+}
+
+impl Turns {
+    fn new(wallets: usize) -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            stopped: (0..wallets).map(|_| AtomicBool::new(false)).collect(),
+        }
+    }
+    fn is_turn(&self, index: usize) -> bool {
+        self.current.load(Ordering::SeqCst) == index
+    }
+    /// passes the turn from `index` to the next wallet still depositing
+    fn pass(&self, index: usize) {
+        let wallets = self.stopped.len();
+        for offset in 1..=wallets {
+            let next = (index + offset) % wallets;
+            if !self.stopped[next].load(Ordering::SeqCst) {
+                let _ = self
+                    .current
+                    .compare_exchange(index, next, Ordering::SeqCst, Ordering::SeqCst);
+                return;
+            }
+        }
+    }
+    fn stop(&self, index: usize) {
+        self.stopped[index].store(true, Ordering::SeqCst);
+        self.pass(index);
+    }
+    /// the number of wallets still depositing
+    fn active(&self) -> usize {
+        self.stopped
+            .iter()
+            .filter(|stopped| !stopped.load(Ordering::SeqCst))
+            .count()
+    }
+}
+
+/// how long pre-signed deposits are kept before being re-signed with fresh quotes
+const PRESIGN_REFRESH: std::time::Duration = std::time::Duration::from_secs(30);
+/// how often the nonce is checked for having been advanced by something else
+const NONCE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// compute unit limit for a pre-signed deposit that can't be simulated, as add liquidity
+/// fails simulation while the pool is full
+const ADD_LIQUIDITY_COMPUTE_UNITS: u32 = 400_000;
+
+/// a deposit signed ahead of time against a durable nonce
+struct PresignedDeposit {
+    /// custody tokens deposited
+    amount: u64,
+    expected_lp_out: u64,
+    min_lp_out: u64,
+    tx: VersionedTransaction,
+}
+
+/// deposits signed against the same nonce, only one of which can ever land
+struct Presigned {
+    nonce: DurableNonce,
+    signed_at: std::time::Instant,
+    checked_at: std::time::Instant,
+    /// ascending by amount
+    deposits: Vec<PresignedDeposit>,
+    /// the custody token price, the owner's room under the per-user jlp limit and the jlp
+    /// mint's decimals and price at signing, so broadcasting needs no rpc round trips
+    price: u64,
+    lp_room: Option<u64>,
+    lp_decimals: u8,
+    jlp_price: f64,
+}
+
+/// what was sent for a deposit, recorded in the ledger
+struct DepositRecord {
+    amount: u64,
+    /// the deposit token and amount swapped into the custody token, if pre-swapped
+    pre_swap: Option<(Pubkey, u64)>,
+    expected_lp_out: u64,
+    min_lp_out: u64,
+    jlp_price: f64,
+    pool_aum_usd: u128,
+    /// oracle price of the custody token, used to charge the budget
+    price: u64,
+}
+
+/// settings and accounts shared by every wallet's deposit pipeline
+struct DepositSettings {
+    rpc: Arc<RpcClient>,
+    jlp_account_cache: JLPCacheAccountKeys,
+    ledger: Arc<Ledger>,
+    force: bool,
+    skip_capacity_check: bool,
+    dry_run: bool,
+    slippage_bps: u64,
+    commitment: CommitmentConfig,
+    swap_back_policy: SwapBackPolicy,
+    /// cloned so every wallet gets its own budget
+    budget: DepositBudget,
+    ui_deposit_amount: u64,
+    deposit_mint: Pubkey,
+    deposit_decimals: u8,
+    /// set when the deposit token isn't a custody and is swapped into this custody first
+    pre_swap_custody: Option<Pubkey>,
+    custody_mint: Pubkey,
+    custody_decimals: u8,
+    priority_fee: PriorityFeeEstimator,
+    /// pool, custody and custody token accounts, which deposits compete to write lock
+    fee_accounts: Vec<Pubkey>,
+    /// shared by every wallet, whose deposits differ in accounts and so are sized separately
+    compute_units: ComputeUnitEstimator,
+    submitter: Arc<dyn Submitter>,
+    wallet_mode: WalletMode,
+    turns: Turns,
+    /// set when deposits are pre-signed against each wallet's nonce account derived from this seed
+    nonce_seed: Option<String>,
+    /// percentages of the deposit amount deposits are pre-signed at
+    presign_percents: Vec<u8>,
+}
+
+/// what a deposit pipeline does after a pass of the deposit loop
+enum Step {
+    /// wait for the next pool change or tick
+    Wait,
+    /// stop depositing from this wallet
+    Stop,
+}
+
+/// deposits from a single wallet, with its own budget and swap-back task
+struct DepositPipeline {
+    settings: Arc<DepositSettings>,
+    index: usize,
+    swapper: Arc<Swapper>,
+    keypair: Keypair,
+    owner: Pubkey,
+    budget: DepositBudget,
+    pre_swapper: Option<PreSwapper>,
+    sender: TransactionSender,
+    create_lp_ata_ix: Option<Instruction>,
+    nonce_account: Option<Pubkey>,
+    presigned: Option<Presigned>,
+}
 
 pub async fn auto_deposit(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
     let conf = Configuration::load(conf_path)?;
-    let rpc = conf.rpc();
-    let keypair_contents = conf.keypair.contents();
-    // expect it to be pk
-    let keypair = Keypair::from_base58_string(&keypair_contents);
-    let owner = keypair.pubkey();
-
-    let swapper = Arc::new(Swapper::new(
-        Arc::new(rpc),
-        keypair
-    ));
-    let keypair = swapper.keypair();
+    let rpc = Arc::new(conf.rpc());
+    let keypairs = conf.load_keypairs()?;
+    let wallet_mode = WalletMode::from_str(matches.get_one::<String>("wallet-mode").unwrap())?;
+    log::info!("depositing from {} wallets, wallet mode {wallet_mode:?}", keypairs.len());
+
     let force = matches.get_flag("force");
     let skip_capacity_check = matches.get_flag("skip-capacity-check");
+    let dry_run = matches.get_flag("dry-run");
 
-    let priority_fee = matches.get_one::<f64>("priority-fee").unwrap();
-    let priority_fee = spl_token::ui_amount_to_amount(*priority_fee, 9);
+    let slippage_bps = *matches.get_one::<u64>("slippage-bps").unwrap();
+    let commitment = CommitmentConfig::from_str(matches.get_one::<String>("commitment").unwrap())?;
+    let swap_back_policy = match matches.get_one::<String>("swap-back") {
+        Some(policy) => SwapBackPolicy::from_str(policy)?,
+        None => conf.swap_back,
+    };
+    log::info!("swap back policy {swap_back_policy}");
+
+    let usd_flag = |name: &str| {
+        matches
+            .get_one::<f64>(name)
+            .map(|usd| spl_token::ui_amount_to_amount(*usd, USD_DECIMALS) as u128)
+    };
+    let budget_window = std::time::Duration::from_secs(*matches.get_one::<u64>("budget-window-secs").unwrap());
+    let budget = DepositBudget::new(
+        usd_flag("max-total-usd"),
+        usd_flag("max-window-usd").map(|usd| (budget_window, usd)),
+        matches.get_one::<usize>("max-deposits").copied(),
+    );
+
+    let fee_conf = conf.priority_fee;
+    let priority_fee = PriorityFeeEstimator::new(rpc.clone())
+        .with_percentile(
+            matches
+                .get_one::<u8>("priority-fee-percentile")
+                .copied()
+                .unwrap_or(fee_conf.percentile),
+        )
+        .with_floor(
+            matches
+                .get_one::<u64>("min-priority-fee")
+                .copied()
+                .unwrap_or(fee_conf.min_micro_lamports),
+        )
+        .with_ceiling(
+            matches
+                .get_one::<u64>("max-priority-fee")
+                .copied()
+                .unwrap_or(fee_conf.max_micro_lamports),
+        )
+        .with_escalation_bps(fee_conf.escalation_bps);
 
     let deposit_amount = matches.get_one::<f64>("deposit-amount").unwrap();
 
@@ -47,246 +279,1098 @@ pub async fn auto_deposit(matches: &clap::ArgMatches, conf_path: &str) -> Result
         deposit_amount
     );
 
-    let acct_data = swapper.rpc.get_account_data(&deposit_mint).await?;
+    let acct_data = rpc.get_account_data(&deposit_mint).await?;
     let deposit_mint_acct = spl_token::state::Mint::unpack(&acct_data[..])?;
     let ui_deposit_amount =
         spl_token::ui_amount_to_amount(*deposit_amount, deposit_mint_acct.decimals);
 
-    let mut ticker = tokio::time::interval(std::time::Duration::from_millis(250));
     let pool_acct = Pubkey::from_str(POOL_ACCT).unwrap();
+    let pool_rx = PoolWatcher::new(
+        rpc.clone(),
+        conf.ws_url(),
+        pool_acct,
+        std::time::Duration::from_millis(250),
+    )
+    .spawn();
     let perp_acct = Pubkey::from_str(PERPETUALS_ACCT).unwrap();
-    let jlp_account_cache =
-        perpetuals::jlp_cacher::JLPCacheAccountKeys::load_account_keys(&swapper.rpc, perp_acct, pool_acct)
-            .await?;
+    let jlp_account_cache = JLPCacheAccountKeys::load_account_keys(&rpc, perp_acct, pool_acct).await?;
+    // tokens that aren't custodies are swapped into a custody token within the deposit transaction
+    let pre_swap_custody = if jlp_account_cache.custody_account_for_mint(deposit_mint).is_none() {
+        let custody_mint = Pubkey::from_str(matches.get_one::<String>("custody-mint").unwrap())?;
+        if jlp_account_cache.custody_account_for_mint(custody_mint).is_none() {
+            return Err(anyhow!(
+                "{custody_mint} is not a jlp custody token, expected one of {:?}",
+                jlp_account_cache
+                    .custody_accounts
+                    .iter()
+                    .map(|custody| custody.mint.to_string())
+                    .collect::<Vec<_>>()
+            ));
+        }
+        log::info!("{deposit_mint} is not a jlp custody token, swapping into {custody_mint} before depositing");
+        Some(custody_mint)
+    } else {
+        None
+    };
+    let custody_mint = pre_swap_custody.unwrap_or(deposit_mint);
+    let custody = jlp_account_cache
+        .custody_account_for_mint(custody_mint)
+        .with_context(|| format!("{custody_mint} is not a jlp custody token"))?;
+    // fees are estimated from the accounts every deposit write locks, whichever wallet sends it
+    let fee_accounts = vec![jlp_account_cache.pool, custody.account, custody.token_account];
+    let custody_decimals = if custody_mint == deposit_mint {
+        deposit_mint_acct.decimals
+    } else {
+        spl_token::state::Mint::unpack(&rpc.get_account_data(&custody_mint).await?[..])?.decimals
+    };
+    // pre-swapped deposits only know the custody amount once quoted
+    if pre_swap_custody.is_none() {
+        if let Err(err) = crate::quote_deposit::warn_if_costlier(
+            &rpc,
+            &jlp_account_cache,
+            custody_mint,
+            ui_deposit_amount,
+        )
+        .await
+        {
+            log::warn!("failed to predict the deposit fee {err:#?}");
+        }
+    }
 
-    let unit_price_ix = ComputeBudgetInstruction::set_compute_unit_price(priority_fee);
-    let cu_ix = ComputeBudgetInstruction::set_compute_unit_limit(400_000);
+    let mut compute_units = ComputeUnitEstimator::new(rpc.clone());
+    if let Some(margin_bps) = matches
+        .get_one::<u64>("compute-unit-margin-bps")
+        .copied()
+        .or(conf.compute_unit_margin_bps)
+    {
+        compute_units = compute_units.with_margin_bps(margin_bps);
+    }
 
-    // create the ata account if needed
+    let submitter = conf.submitter(rpc.clone()).await?;
 
-    if let Some(ix) = jlp_account_cache.create_lp_token_ata_ix(&swapper.rpc, owner).await {
-        let mut tx = Transaction::new_with_payer(&[ix], Some(&owner));
-        tx.sign(&vec![&keypair], swapper.rpc.get_latest_blockhash().await?);
-        let sig = swapper.rpc.send_and_confirm_transaction(&tx).await?;
-        log::info!("sent create ata tx {}", sig);
-    }
+    let nonce_seed = if matches.get_flag("durable-nonce") {
+        if pre_swap_custody.is_some() {
+            return Err(anyhow!("durable nonce deposits can't pre-swap, the swap quote would go stale"));
+        }
+        Some(matches.get_one::<String>("nonce-seed").unwrap().clone())
+    } else {
+        None
+    };
+    let presign_percents = matches
+        .get_many::<u8>("presign-percents")
+        .unwrap()
+        .copied()
+        .collect();
+
+    let wallets = keypairs.len();
+    let settings = Arc::new(DepositSettings {
+        rpc,
+        jlp_account_cache,
+        ledger: Arc::new(Ledger::new(conf.ledger_path())),
+        force,
+        skip_capacity_check,
+        dry_run,
+        slippage_bps,
+        commitment,
+        swap_back_policy,
+        budget,
+        ui_deposit_amount,
+        deposit_mint,
+        deposit_decimals: deposit_mint_acct.decimals,
+        pre_swap_custody,
+        custody_mint,
+        custody_decimals,
+        priority_fee,
+        fee_accounts,
+        compute_units,
+        submitter,
+        wallet_mode,
+        turns: Turns::new(wallets),
+        nonce_seed,
+        presign_percents,
+    });
 
     let mut sig_int = tokio::signal::unix::signal(SignalKind::interrupt())?;
     let mut sig_quit = tokio::signal::unix::signal(SignalKind::quit())?;
     let mut sig_term = tokio::signal::unix::signal(SignalKind::terminate())?;
 
-    let (swap_tx, swap_rx) = tokio::sync::mpsc::channel::<()>(128);
-    let (exit_tx, exit_rx) = tokio::sync::oneshot::channel();
-
-    let usdc_ata = spl_associated_token_account::get_associated_token_address(
-        &keypair.pubkey(),
-        &deposit_mint
-    );
+    // cap raises are recorded while depositing, so past openings are known to capacity watch
+    let capacity_task =
+        CapacityWatcher::new(settings.ledger.clone(), pool_acct).spawn(pool_rx.clone());
 
-    {
-        let swapper = swapper.clone();
-        tokio::task::spawn(async move {
-            if let Err(err) = swapi_boi(swapper, swap_rx, exit_rx).await {
-                log::error!("{err:#?}");
-            }
-        });
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut pipelines = tokio::task::JoinSet::new();
+    for (index, keypair) in keypairs.into_iter().enumerate() {
+        let pipeline = DepositPipeline::new(settings.clone(), index, keypair).await?;
+        pipelines.spawn(pipeline.run(pool_rx.clone(), shutdown_rx.clone()));
     }
+    let mut result = Ok(());
     loop {
         tokio::select! {
             biased;
             _ = sig_int.recv() => {
                 log::info!("goodbye");
-                let _ = exit_tx.send(());
-                return Ok(());
+                let _ = shutdown_tx.send(true);
             }
             _ = sig_quit.recv() => {
                 log::info!("goodbye");
-                let _ = exit_tx.send(());
-                return Ok(());
+                let _ = shutdown_tx.send(true);
             }
             _ = sig_term.recv() => {
                 log::info!("goodbye");
-                let _ = exit_tx.send(());
-                return Ok(());
+                let _ = shutdown_tx.send(true);
             }
-            _ = ticker.tick() => {
+            joined = pipelines.join_next() => {
+                let err = match joined {
+                    None => {
+                        capacity_task.abort();
+                        return result;
+                    }
+                    Some(Ok(Ok(()))) => continue,
+                    Some(Ok(Err(err))) => err,
+                    Some(Err(err)) => anyhow!("deposit pipeline panicked {err:#?}"),
+                };
+                log::error!("{err:#?}");
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+    }
+}
+
+impl DepositPipeline {
+    async fn new(settings: Arc<DepositSettings>, index: usize, keypair: Keypair) -> Result<Self> {
+        let owner = keypair.pubkey();
+        let swapper = Arc::new(
+            Swapper::new(settings.rpc.clone(), keypair)
+                .with_priority_fee(settings.priority_fee.clone())
+                .with_compute_units(settings.compute_units.clone())
+                .with_submitter(settings.submitter.clone()),
+        );
+        let keypair = swapper.keypair();
+        let pre_swapper = match settings.pre_swap_custody {
+            Some(custody_mint) => Some(PreSwapper::new(
+                owner,
+                settings.deposit_mint,
+                custody_mint,
+                settings.slippage_bps,
+            )?),
+            None => None,
+        };
+        let create_lp_ata_ix = settings
+            .jlp_account_cache
+            .create_lp_token_ata_ix(&settings.rpc, owner)
+            .await;
+        let nonce_account = match &settings.nonce_seed {
+            Some(seed) => {
+                let nonce_account = nonce_address(&owner, seed)?;
+                DurableNonce::fetch(&settings.rpc, nonce_account, CommitmentConfig::confirmed())
+                    .await
+                    .with_context(|| format!("no nonce account for {owner}, create one with the nonce create command"))?;
+                Some(nonce_account)
+            }
+            None => None,
+        };
+        Ok(Self {
+            budget: settings.budget.clone(),
+            sender: TransactionSender::new(settings.rpc.clone(), settings.commitment)
+                .with_priority_fee(settings.priority_fee.clone(), settings.fee_accounts.clone())
+                .with_submitter(settings.submitter.clone()),
+            settings,
+            index,
+            swapper,
+            keypair,
+            owner,
+            pre_swapper,
+            create_lp_ata_ix,
+            nonce_account,
+            presigned: None,
+        })
+    }
 
+    /// runs the deposit loop until shutdown, the pool watcher stopping or the wallet
+    /// reaching its budget or personal jlp cap
+    async fn run(
+        mut self,
+        mut pool_rx: watch::Receiver<Option<PoolUpdate>>,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<()> {
+        let owner = self.owner;
+        // stop rebroadcasting an in-flight deposit on shutdown rather than waiting for it to expire
+        self.sender = self.sender.clone().with_shutdown(shutdown_rx.clone());
+        // create the ata account if needed, when dry running it is included in the simulated transaction instead
+        if !self.settings.dry_run {
+            if let Some(ix) = self.create_lp_ata_ix.clone() {
+                let mut tx = Transaction::new_with_payer(&[ix], Some(&owner));
+                tx.sign(&vec![&self.keypair], self.settings.rpc.get_latest_blockhash().await?);
+                let sig = self.settings.rpc.send_and_confirm_transaction(&tx).await?;
+                log::info!("sent create ata tx {} for {owner}", sig);
             }
         }
-        let jlp_accounts = jlp_account_cache.load_accounts(&swapper.rpc, usdc_ata).await?;
-        let jlp_price = jlp_accounts.calculate_jlp_price();
 
-        // compute the min jlp token per usdc
-        let min_jlp_per_usdc = 1.0 / jlp_price;
+        let (swap_tx, swap_rx) = tokio::sync::mpsc::channel::<DepositLanded>(128);
+        let (exit_tx, exit_rx) = tokio::sync::oneshot::channel();
+        let (report_tx, mut report_rx) = tokio::sync::mpsc::channel::<SwapBackReport>(128);
+
+        let swap_task = if !self.settings.dry_run {
+            let swap_back = SwapBack::new(
+                self.swapper.clone(),
+                self.settings.jlp_account_cache.clone(),
+                self.settings.swap_back_policy,
+                self.settings.commitment,
+            )?;
+            let ledger = self.settings.ledger.clone();
+            Some(tokio::task::spawn(async move {
+                if let Err(err) =
+                    crate::swap_back::swapi_boi(swap_back, ledger, swap_rx, report_tx, exit_rx).await
+                {
+                    log::error!("{err:#?}");
+                }
+            }))
+        } else {
+            None
+        };
+        let mut ticker = tokio::time::interval(std::time::Duration::from_millis(250));
+        loop {
+            tokio::select! {
+                biased;
+                _ = shutdown_rx.changed() => {
+                    let _ = exit_tx.send(());
+                    return Ok(());
+                }
+                changed = pool_rx.changed() => {
+                    if changed.is_err() {
+                        let _ = exit_tx.send(());
+                        return Err(anyhow!("pool watcher stopped"));
+                    }
+                }
+                Some(report) = report_rx.recv() => {
+                    log::info!("swap back for {owner} finished {report}");
+                }
+                _ = ticker.tick() => {
+
+                }
+            }
+            match self.deposit(&pool_rx, &swap_tx).await {
+                Ok(Step::Wait) => {}
+                Ok(Step::Stop) => {
+                    self.settings.turns.stop(self.index);
+                    wait_for_swaps(swap_tx, swap_task).await;
+                    return Ok(());
+                }
+                Err(err) => {
+                    self.settings.turns.stop(self.index);
+                    let _ = exit_tx.send(());
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// gives the next wallet its turn once this wallet has deposited or had nothing to deposit
+    fn pass_turn(&self) {
+        if self.settings.wallet_mode == WalletMode::RoundRobin {
+            self.settings.turns.pass(self.index);
+        }
+    }
+
+    /// makes a single deposit attempt if the pool has room
+    async fn deposit(
+        &mut self,
+        pool_rx: &watch::Receiver<Option<PoolUpdate>>,
+        swap_tx: &tokio::sync::mpsc::Sender<DepositLanded>,
+    ) -> Result<Step> {
+        let settings = self.settings.clone();
+        let owner = self.owner;
+        let custody_mint = settings.custody_mint;
+        let custody_decimals = settings.custody_decimals;
+        if let Err(err) = self.refresh_presigned().await {
+            log::error!("failed to pre-sign deposits from {owner} {err:#?}");
+        }
+        // only hit the rpc for balances and quotes once the watcher reports room in the pool
+        let capacity_slot = pool_rx
+            .borrow()
+            .as_ref()
+            .filter(|update| update.has_capacity())
+            .map(|update| update.slot);
+        match capacity_slot {
+            Some(slot) => log::debug!("pool has capacity as of slot {slot}"),
+            None if !settings.skip_capacity_check => return Ok(Step::Wait),
+            None => {}
+        }
+        if settings.wallet_mode == WalletMode::RoundRobin && !settings.turns.is_turn(self.index) {
+            return Ok(Step::Wait);
+        }
+        let budget_usd = match self.budget.status(std::time::Instant::now()) {
+            BudgetStatus::Available(usd) => usd,
+            BudgetStatus::Paused(resumes_at) => {
+                log::debug!(
+                    "deposit window budget for {owner} used, resuming in {:?}",
+                    resumes_at.saturating_duration_since(std::time::Instant::now())
+                );
+                self.pass_turn();
+                return Ok(Step::Wait);
+            }
+            BudgetStatus::Exhausted(reason) => {
+                log::info!("deposit budget for {owner} exhausted, {reason}, waiting for pending swaps");
+                return Ok(Step::Stop);
+            }
+        };
+        if self.nonce_account.is_some() {
+            // sized from the watched pool and the state cached at signing, without hitting the rpc
+            let Some(update) = pool_rx.borrow().clone() else {
+                return Ok(Step::Wait);
+            };
+            return self.deposit_presigned(budget_usd, &update.pool, swap_tx).await;
+        }
+        let jlp_accounts = settings
+            .jlp_account_cache
+            .load_accounts(&settings.rpc, owner, settings.deposit_mint)
+            .await?;
+        let jlp_price = jlp_accounts.calculate_jlp_price();
 
         log::info!(
-            "aum {}, aum_max {}, jlp_price {jlp_price}, jlp_per_usdc {min_jlp_per_usdc}",
+            "aum {}, aum_max {}, jlp_price {jlp_price}",
             jlp_accounts.pool.aum_usd,
             jlp_accounts.pool.limit.max_aum_usd
         );
 
-        if jlp_accounts.pool.aum_usd >= jlp_accounts.pool.limit.max_aum_usd && !skip_capacity_check {
+        if jlp_accounts.pool.aum_usd >= jlp_accounts.pool.limit.max_aum_usd && !settings.skip_capacity_check {
             log::debug!("max deposit cap reached");
-            continue;
+            return Ok(Step::Wait);
+        }
+        // the program rejects deposits that would take the owner over the pool's per-user jlp limit
+        let lp_room = jlp_accounts.room_for_lp_tokens();
+        if lp_room == Some(0) {
+            log::warn!(
+                "personal jlp cap reached for {owner}, holding {} jlp of the pool's per-user limit of {} jlp, stopping deposits",
+                spl_token::amount_to_ui_amount(jlp_accounts.lp_balance, jlp_accounts.token_mint.decimals),
+                jlp_accounts.pool.limit.max_individual_lp_token as f64
+                    / 10_f64.powi(jlp_accounts.token_mint.decimals as i32),
+            );
+            return Ok(Step::Stop);
         }
 
-        let (deposit_amount, min_out) = if force {
-            (
-                ui_deposit_amount,
-                spl_token::ui_amount_to_amount(
-                    (*deposit_amount * min_jlp_per_usdc) * 0.99, // 1% slip
-                    deposit_mint_acct.decimals,
-                ),
-            )
+        // price the custody token from its oracle so usd capacity and budgets convert into token units
+        let price = match settings
+            .jlp_account_cache
+            .oracle_price(&settings.rpc, owner, custody_mint)
+            .await
+        {
+            Ok(price) => price.buy_lp,
+            Err(err) => {
+                log::error!("failed to fetch oracle price {err:#?}");
+                return Ok(Step::Wait);
+            }
+        };
+        let personal_cap_amount = match lp_room {
+            Some(lp_room) => usd_to_token_amount(
+                jlp_accounts.lp_to_usd_amount(lp_room),
+                price,
+                custody_decimals,
+            ),
+            None => u64::MAX,
+        };
+        let custody_cap = self
+            .custody_cap(jlp_accounts.room_for_deposit_usd(), budget_usd, price)
+            .min(personal_cap_amount);
+        // available capacity less than deposit amount so override deposit amount with capacity,
+        // and if the available room is more than our current balance, overwrite with our balance
+        let input_amount = if settings.force {
+            settings.ui_deposit_amount
         } else {
-            let room_for_deposit_usd =
-                jlp_accounts.pool.limit.max_aum_usd - jlp_accounts.pool.aum_usd;
-            let deposit_amount = if (room_for_deposit_usd as f64) < (*deposit_amount) {
-                // available capacity less than deposit amount so override deposit amount with capacity
-                room_for_deposit_usd as f64
-            } else {
-                // use deposit
-                *deposit_amount
-            };
-            let cur_bal = spl_token::amount_to_ui_amount(jlp_accounts.usdc_token_account.amount, deposit_mint_acct.decimals);
-            // if the available room is more than our current balance, overwrite with our balance
-            let deposit_amount = if deposit_amount > cur_bal {
-                cur_bal
-            } else {
-                deposit_amount
-            };
-            (
-                spl_token::ui_amount_to_amount(deposit_amount, deposit_mint_acct.decimals),
-                spl_token::ui_amount_to_amount(
-                    (deposit_amount * min_jlp_per_usdc) * 0.99, // 1% slip
-                    deposit_mint_acct.decimals,
-                ),
-            )
+            settings
+                .ui_deposit_amount
+                .min(jlp_accounts.deposit_balance(settings.deposit_mint))
+        };
+        let (mut deposit_amount, pre_swap) = match &self.pre_swapper {
+            None => (input_amount.min(custody_cap), None),
+            Some(pre_swapper) => match pre_swapper.quote(input_amount, custody_cap).await {
+                Ok(Some(pre_swap)) => (pre_swap.custody_amount, Some(pre_swap)),
+                Ok(None) => (0, None),
+                Err(err) => {
+                    log::error!("failed to quote pre-swap {err:#?}");
+                    return Ok(Step::Wait);
+                }
+            },
+        };
+        if deposit_amount == 0 {
+            log::debug!("nothing to deposit from {owner}");
+            self.pass_turn();
+            return Ok(Step::Wait);
+        }
+
+        // quote the exact amount of jlp minted from the program itself, accounting for the add liquidity fee and tax
+        let mut quote = None;
+        for _ in 0..2 {
+            match settings
+                .jlp_account_cache
+                .quote_add_liquidity(&settings.rpc, owner, custody_mint, deposit_amount)
+                .await
+            {
+                Ok(quoted) => match lp_room {
+                    // the oracle price can differ from the pool's aum price, so shrink the deposit to fit.
+                    // a pre-swapped deposit is fixed by its swap quote, which was already capped
+                    Some(lp_room) if quoted.amount > lp_room && pre_swap.is_none() => {
+                        deposit_amount = (deposit_amount as u128 * lp_room as u128
+                            / quoted.amount as u128) as u64;
+                    }
+                    _ => {
+                        quote = Some(quoted);
+                        break;
+                    }
+                },
+                Err(err) => {
+                    log::error!("failed to quote add liquidity {err:#?}");
+                    break;
+                }
+            }
+        }
+        let quote = match quote {
+            Some(quote) if deposit_amount > 0 => quote,
+            _ => return Ok(Step::Wait),
         };
+        let min_out = apply_slippage(quote.amount, settings.slippage_bps);
 
+        if let Some(pre_swap) = &pre_swap {
+            log::info!(
+                "swapping {} {} into at least {} {}",
+                spl_token::amount_to_ui_amount(pre_swap.input_amount, settings.deposit_decimals),
+                settings.deposit_mint,
+                spl_token::amount_to_ui_amount(pre_swap.custody_amount, custody_decimals),
+                custody_mint,
+            );
+        }
         log::info!(
-            "depositing {} usdc for expected {} jlp",
-            deposit_amount,
-            min_out
+            "depositing {} {} from {owner} for expected {} jlp (fee {} bps), min out {} jlp",
+            spl_token::amount_to_ui_amount(deposit_amount, custody_decimals),
+            custody_mint,
+            spl_token::amount_to_ui_amount(quote.amount, jlp_accounts.token_mint.decimals),
+            quote.fee_bps,
+            spl_token::amount_to_ui_amount(min_out, jlp_accounts.token_mint.decimals),
         );
 
-        let add_liq_ix = jlp_account_cache.generate_liquidity_add_ix(
-            deposit_mint,
+        let add_liq_ix = settings.jlp_account_cache.generate_liquidity_add_ix(
+            custody_mint,
             owner,
             deposit_amount,
             min_out,
+            pre_swap.as_ref().map(|pre_swap| pre_swap.input_amount),
         )?;
 
-        let mut tx = Transaction::new_with_payer(
-            &[unit_price_ix.clone(), cu_ix.clone(), add_liq_ix],
-            Some(&owner),
-        );
-        tx.sign(&vec![&keypair], swapper.rpc.get_latest_blockhash().await?);
-        for _ in 0..3 {
-            match swapper.rpc
-            .send_transaction_with_config(
-                &tx,
-                solana_client::rpc_config::RpcSendTransactionConfig {
-                    skip_preflight: false,
-                    ..Default::default()
-                },
-            )
-            .await {
-                Ok(sig) => {
-                    log::info!("sent add liquidity {}", sig);
-                    if let Err(err) = swap_tx.send(()).await {
-                        log::error!("failed to send swap notification {err:#?}");
-                    }
-                    break;
+        // compute budget instructions are prepended once the transaction is sized and priced
+        let mut ixs = vec![];
+        if settings.dry_run {
+            ixs.extend(self.create_lp_ata_ix.clone());
+        }
+        let mut luts = vec![];
+        if let Some(pre_swap) = &pre_swap {
+            // the swap has to run before add liquidity so its output funds the deposit
+            match pre_swap.instructions() {
+                Ok(swap_ixs) => ixs.extend(swap_ixs),
+                Err(err) => {
+                    log::error!("failed to decode pre-swap instructions {err:#?}");
+                    return Ok(Step::Wait);
                 }
+            }
+            luts = match pre_swap.address_lookup_tables(&settings.rpc).await {
+                Ok(luts) => luts,
                 Err(err) => {
-                    log::error!("failed to send add liq tx {err:#?}, retrying...");
-                    tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+                    log::error!("failed to load pre-swap lookup tables {err:#?}");
+                    return Ok(Step::Wait);
                 }
+            };
+        } else if settings.deposit_mint.eq(&spl_token::native_mint::id()) {
+            // wrap whatever the wrapped sol account is missing
+            let wrapped_balance = jlp_accounts
+                .funding_token_account
+                .map(|tkn_acct| tkn_acct.amount)
+                .unwrap_or_default();
+            let shortfall = deposit_amount.saturating_sub(wrapped_balance);
+            if shortfall > 0 {
+                ixs.extend(wrap_sol_ixs(owner, shortfall));
             }
         }
-    }
-}
-
+        ixs.push(add_liq_ix);
+        if custody_mint.eq(&spl_token::native_mint::id()) {
+            // unwrap the remainder once deposited
+            ixs.push(unwrap_sol_ix(owner));
+        }
+        ixs.extend(self.sender.tip_instructions(&owner));
 
-async fn swapi_boi(
-    swapper: Arc<Swapper>, 
-    mut swap_trigger: tokio::sync::mpsc::Receiver<()>,
-    mut exit_rx: tokio::sync::oneshot::Receiver<()>,
-) -> anyhow::Result<()> {
-    let keypair = swapper.keypair();
-    let owner = keypair.pubkey();
-    let owner_str = owner.to_string();
-    let jlp_ata = spl_associated_token_account::get_associated_token_address(
-        &owner,
-        &LP_TOKEN_MINT
-    );
+        // simulated once per transaction shape, the cached limit is reused by later deposits
+        let cu_limit = settings.compute_units.limit(owner, &ixs, &luts).await;
+        let priced = |priority_fee: u64| {
+            [
+                ComputeBudgetInstruction::set_compute_unit_limit(cu_limit),
+                ComputeBudgetInstruction::set_compute_unit_price(priority_fee),
+            ]
+            .into_iter()
+            .chain(ixs.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        if settings.dry_run {
+            let priority_fee = settings.priority_fee.estimate(&settings.fee_accounts).await?;
+            log::info!("estimated priority fee {priority_fee} micro-lamports per compute unit");
+            let v0_msg = compile_v0_message(owner, &priced(priority_fee), &luts, settings.rpc.get_latest_blockhash().await?)?;
+            let tx = VersionedTransaction::try_new(VersionedMessage::V0(v0_msg.clone()), &vec![&self.keypair])?;
+            simulate_deposit(&settings.rpc, &tx, &v0_msg, owner, jlp_accounts.token_mint.decimals).await?;
+            self.pass_turn();
+            return Ok(Step::Stop);
+        }
+        // rebroadcasts until the deposit lands, re-signing if the blockhash expires first
+        let keypair = &self.keypair;
+        let send_result = self
+            .sender
+            .send(|attempt| {
+                let v0_msg = compile_v0_message(owner, &priced(attempt.priority_fee), &luts, attempt.blockhash)?;
+                Ok(VersionedTransaction::try_new(VersionedMessage::V0(v0_msg), &vec![keypair])?)
+            })
+            .await;
+        self.pass_turn();
+        self.record_sent(
+            DepositRecord {
+                amount: deposit_amount,
+                pre_swap: pre_swap
+                    .as_ref()
+                    .map(|pre_swap| (settings.deposit_mint, pre_swap.input_amount)),
+                expected_lp_out: quote.amount,
+                min_lp_out: min_out,
+                jlp_price,
+                pool_aum_usd: jlp_accounts.pool.aum_usd,
+                price,
+            },
+            send_result,
+            swap_tx,
+        )
+        .await;
+        Ok(Step::Wait)
+    }
 
-    let swap_api = jupiter_api::client::Client::new()?;
+    /// returns the custody tokens that can be deposited at `price` into `room_usd` of pool
+    /// capacity within `budget_usd`, all usd scaled by USD_DECIMALS
+    fn custody_cap(&self, room_usd: u128, budget_usd: u128, price: u64) -> u64 {
+        let settings = &self.settings;
+        // clamped so an unlimited budget doesn't overflow the conversion
+        let budget_amount = usd_to_token_amount(
+            budget_usd.min(u64::MAX as u128),
+            price,
+            settings.custody_decimals,
+        );
+        if settings.force {
+            return budget_amount;
+        }
+        let room_usd = match settings.wallet_mode {
+            // every wallet still depositing does so at once, so each takes an equal share of the opening
+            WalletMode::Split => room_usd / settings.turns.active().max(1) as u128,
+            WalletMode::RoundRobin => room_usd,
+        };
+        usd_to_token_amount(room_usd, price, settings.custody_decimals).min(budget_amount)
+    }
 
-    loop {
-        log::info!("waiting for swap requests");
-        tokio::select! {
-            biased;
-            _ = swap_trigger.recv() => {},
-            _ = &mut exit_rx => {
-                log::info!("swapi_boi goodbye");
-                return Ok(());
+    /// records a sent deposit in the ledger and budget, reporting it to the swap task once final
+    async fn record_sent(
+        &mut self,
+        record: DepositRecord,
+        send_result: Result<Sent>,
+        swap_tx: &tokio::sync::mpsc::Sender<DepositLanded>,
+    ) {
+        let settings = self.settings.clone();
+        let owner = self.owner;
+        match &send_result {
+            Ok(sent) => log::info!("sent add liquidity {}", sent.signature),
+            Err(err) => log::error!("failed to send add liq tx {err:#?}"),
+        }
+        settings.ledger.record(
+            &owner.to_string(),
+            LedgerEvent::Deposit {
+                deposit_mint: settings.custody_mint.to_string(),
+                amount_in: record.amount,
+                pre_swap_mint: record.pre_swap.map(|(mint, _)| mint.to_string()),
+                pre_swap_amount: record.pre_swap.map(|(_, amount)| amount),
+                expected_lp_out: record.expected_lp_out,
+                min_lp_out: record.min_lp_out,
+                jlp_price: record.jlp_price,
+                pool_aum_usd: u64::try_from(record.pool_aum_usd).unwrap_or(u64::MAX),
+                signature: send_result.as_ref().ok().map(|sent| sent.signature.to_string()),
+                error: send_result.as_ref().err().map(|err| format!("{err:#}")),
+            },
+        );
+        if let Ok(sent) = send_result {
+            let pending = sent.outcome == TxOutcome::Pending;
+            // a pending deposit may yet land, so it counts against the budget too
+            if sent.outcome.is_landed() || pending {
+                self.budget.record(
+                    std::time::Instant::now(),
+                    token_to_usd_amount(record.amount, record.price, settings.custody_decimals),
+                );
+            }
+            // only swap back once the deposit has actually landed
+            let report = report_deposit(
+                self.sender.tracker().clone(),
+                settings.ledger.clone(),
+                owner,
+                sent,
+                record.min_lp_out,
+                swap_tx.clone(),
+            );
+            if pending {
+                // recorded before the pipeline exits, as a spawned task may not get to run
+                report.await;
+            } else {
+                tokio::task::spawn(report);
             }
         }
-        log::info!("swapping...");
-        let jlp_tkn_acct = match swapper.rpc.get_account_data(&jlp_ata).await {
-            Ok(jlp_ata_acct_data) => {
-                match spl_token::state::Account::unpack(&jlp_ata_acct_data) {
-                    Ok(tkn_acct) => {
-                        tkn_acct
-                    }
-                    Err(err) => {
-                        log::error!("failed to unpack jupiter ata {err:#?}");
-                        continue;
-                    }
+    }
+
+    /// discards the pre-signed deposits once stale or once the nonce has been advanced, and
+    /// pre-signs a new set. does nothing unless depositing with a durable nonce
+    async fn refresh_presigned(&mut self) -> Result<()> {
+        let Some(nonce_account) = self.nonce_account else {
+            return Ok(());
+        };
+        if let Some(presigned) = &mut self.presigned {
+            if presigned.signed_at.elapsed() < PRESIGN_REFRESH {
+                if presigned.checked_at.elapsed() < NONCE_CHECK_INTERVAL {
+                    return Ok(());
+                }
+                presigned.checked_at = std::time::Instant::now();
+                let nonce =
+                    DurableNonce::fetch(&self.settings.rpc, nonce_account, CommitmentConfig::confirmed())
+                        .await?;
+                if nonce.blockhash == presigned.nonce.blockhash {
+                    return Ok(());
                 }
+                log::warn!(
+                    "nonce {nonce_account} was advanced, discarding {} pre-signed deposits",
+                    presigned.deposits.len()
+                );
             }
+            self.presigned = None;
+        }
+        self.presigned = Some(self.presign(nonce_account).await?);
+        Ok(())
+    }
+
+    /// signs a deposit for each pre-sign size against the wallet's durable nonce
+    async fn presign(&self, nonce_account: Pubkey) -> Result<Presigned> {
+        let settings = self.settings.clone();
+        let owner = self.owner;
+        let custody_mint = settings.custody_mint;
+        let nonce = DurableNonce::fetch(&settings.rpc, nonce_account, CommitmentConfig::confirmed()).await?;
+        let jlp_accounts = settings
+            .jlp_account_cache
+            .load_accounts(&settings.rpc, owner, settings.deposit_mint)
+            .await?;
+        let price = settings
+            .jlp_account_cache
+            .oracle_price(&settings.rpc, owner, custody_mint)
+            .await?
+            .buy_lp;
+        // pre-signed transactions can't be re-priced, so the fee is fixed at signing
+        let priority_fee = match settings.priority_fee.estimate(&settings.fee_accounts).await {
+            Ok(priority_fee) => priority_fee,
             Err(err) => {
-                log::error!("failed to fetch jupiter ata {err:#?}");
-                continue;
+                log::warn!("failed to estimate priority fee {err:#?}");
+                settings.priority_fee.floor()
             }
         };
-        for _ in 0..3 {
-            match swap_api.new_quote(
-                LP_MINT_STR,
-                USDC_MINT_STR,
-                jlp_tkn_acct.amount,
-                &[]
-            ).await {
-                Ok(quote_response) => {
-                    match swap_api
-                    .new_swap(quote_response, &owner_str, true).await {
-                        Ok(swap_response) => {
-                            match swapper.new_swap(swap_response, false, 5).await {
-                                Ok(sig) => {
-                                    log::info!("sent swap tx {}", sig);
-                                    continue;
-                                }
-                                Err(err) => {
-                                    log::error!("failed to execute swap {err:#?}, retrying...");
-                                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            log::error!("failed to fetch swap data {err:#?}, retrying...");
-                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                        }
-                    }
+        let balance = if settings.force {
+            u64::MAX
+        } else {
+            jlp_accounts.deposit_balance(settings.deposit_mint)
+        };
+        let mut amounts = settings
+            .presign_percents
+            .iter()
+            .map(|percent| (settings.ui_deposit_amount as u128 * *percent as u128 / 100) as u64)
+            .map(|amount| amount.min(balance))
+            .filter(|amount| *amount > 0)
+            .collect::<Vec<_>>();
+        amounts.sort_unstable();
+        amounts.dedup();
+
+        let mut deposits = Vec::with_capacity(amounts.len());
+        for amount in amounts {
+            // the program can't quote a deposit into a full pool, so fall back to the pool's nav.
+            // the add liquidity fee then has to fit within the slippage tolerance
+            let expected_lp_out = match settings
+                .jlp_account_cache
+                .quote_add_liquidity(&settings.rpc, owner, custody_mint, amount)
+                .await
+            {
+                Ok(quote) => quote.amount,
+                Err(_) => jlp_accounts.usd_to_lp_amount(token_to_usd_amount(
+                    amount,
+                    price,
+                    settings.custody_decimals,
+                )),
+            };
+            let min_lp_out = apply_slippage(expected_lp_out, settings.slippage_bps);
+            let mut ixs = vec![];
+            if settings.dry_run {
+                ixs.extend(self.create_lp_ata_ix.clone());
+            }
+            if settings.deposit_mint.eq(&spl_token::native_mint::id()) {
+                let wrapped_balance = jlp_accounts
+                    .funding_token_account
+                    .map(|tkn_acct| tkn_acct.amount)
+                    .unwrap_or_default();
+                let shortfall = amount.saturating_sub(wrapped_balance);
+                if shortfall > 0 {
+                    ixs.extend(wrap_sol_ixs(owner, shortfall));
                 }
+            }
+            ixs.push(settings.jlp_account_cache.generate_liquidity_add_ix(
+                custody_mint,
+                owner,
+                amount,
+                min_lp_out,
+                None,
+            )?);
+            if custody_mint.eq(&spl_token::native_mint::id()) {
+                ixs.push(unwrap_sol_ix(owner));
+            }
+            ixs.extend(self.sender.tip_instructions(&owner));
+            // reuses the limit cached from a deposit of the same shape that did simulate
+            let cu_limit = settings
+                .compute_units
+                .limit_or(owner, &ixs, &[], ADD_LIQUIDITY_COMPUTE_UNITS)
+                .await;
+            // advancing the nonce has to be the first instruction
+            let ixs = [
+                nonce.advance_ix(),
+                ComputeBudgetInstruction::set_compute_unit_limit(cu_limit),
+                ComputeBudgetInstruction::set_compute_unit_price(priority_fee),
+            ]
+            .into_iter()
+            .chain(ixs)
+            .collect::<Vec<_>>();
+            let v0_msg = compile_v0_message(owner, &ixs, &[], nonce.blockhash)?;
+            deposits.push(PresignedDeposit {
+                amount,
+                expected_lp_out,
+                min_lp_out,
+                tx: VersionedTransaction::try_new(VersionedMessage::V0(v0_msg), &vec![&self.keypair])?,
+            });
+        }
+        log::info!(
+            "pre-signed {} deposits from {owner} against nonce {}",
+            deposits.len(),
+            nonce.blockhash
+        );
+        let now = std::time::Instant::now();
+        Ok(Presigned {
+            nonce,
+            signed_at: now,
+            checked_at: now,
+            deposits,
+            price,
+            lp_room: jlp_accounts.room_for_lp_tokens(),
+            lp_decimals: jlp_accounts.token_mint.decimals,
+            jlp_price: jlp_accounts.calculate_jlp_price(),
+        })
+    }
+
+    /// broadcasts the largest pre-signed deposit that fits the pool's room and `budget_usd`
+    /// and whose jlp fits under the personal cap, discarding the rest since broadcasting
+    /// advances the nonce
+    async fn deposit_presigned(
+        &mut self,
+        budget_usd: u128,
+        pool: &perpetuals::Pool,
+        swap_tx: &tokio::sync::mpsc::Sender<DepositLanded>,
+    ) -> Result<Step> {
+        let owner = self.owner;
+        let Some(presigned) = self.presigned.take() else {
+            log::debug!("no pre-signed deposits from {owner} yet");
+            return Ok(Step::Wait);
+        };
+        if presigned.lp_room == Some(0) {
+            log::warn!("personal jlp cap reached for {owner}, stopping deposits");
+            return Ok(Step::Stop);
+        }
+        let room_usd = pool.limit.max_aum_usd.saturating_sub(pool.aum_usd);
+        let custody_cap = self.custody_cap(room_usd, budget_usd, presigned.price);
+        let fits = |deposit: &&PresignedDeposit| {
+            deposit.amount <= custody_cap
+                && deposit.expected_lp_out <= presigned.lp_room.unwrap_or(u64::MAX)
+        };
+        let Some(deposit) = presigned.deposits.iter().rev().find(fits) else {
+            log::debug!("no pre-signed deposit from {owner} fits {custody_cap}");
+            self.presigned = Some(presigned);
+            self.pass_turn();
+            return Ok(Step::Wait);
+        };
+        let lp_decimals = presigned.lp_decimals;
+        log::info!(
+            "depositing pre-signed {} {} from {owner} for expected {} jlp, min out {} jlp",
+            spl_token::amount_to_ui_amount(deposit.amount, self.settings.custody_decimals),
+            self.settings.custody_mint,
+            spl_token::amount_to_ui_amount(deposit.expected_lp_out, lp_decimals),
+            spl_token::amount_to_ui_amount(deposit.min_lp_out, lp_decimals),
+        );
+        if self.settings.dry_run {
+            if let VersionedMessage::V0(v0_msg) = &deposit.tx.message {
+                simulate_deposit(&self.settings.rpc, &deposit.tx, v0_msg, owner, lp_decimals).await?;
+            }
+            self.pass_turn();
+            return Ok(Step::Stop);
+        }
+        // no blockhash fetch or signing, the transaction goes out as signed
+        let mut send_result = self.sender.send_durable(&deposit.tx, presigned.nonce).await;
+        if let Ok(sent) = &mut send_result {
+            if sent.outcome == TxOutcome::Expired {
+                sent.outcome = self.invalidate_presigned(presigned.nonce, &sent.signature).await;
+            }
+        }
+        self.pass_turn();
+        self.record_sent(
+            DepositRecord {
+                amount: deposit.amount,
+                pre_swap: None,
+                expected_lp_out: deposit.expected_lp_out,
+                min_lp_out: deposit.min_lp_out,
+                jlp_price: presigned.jlp_price,
+                pool_aum_usd: pool.aum_usd,
+                price: presigned.price,
+            },
+            send_result,
+            swap_tx,
+        )
+        .await;
+        Ok(Step::Wait)
+    }
+
+    /// advances the nonce an expired pre-signed deposit was signed against so it can't land
+    /// later, returning the deposit's final state. it may have landed before the nonce advanced,
+    /// and is pending when the nonce couldn't be advanced
+    async fn invalidate_presigned(&self, nonce: DurableNonce, signature: &Signature) -> TxOutcome {
+        let owner = self.owner;
+        let keypair = &self.keypair;
+        let ixs = [nonce.advance_ix()]
+            .into_iter()
+            .chain(self.sender.tip_instructions(&owner))
+            .collect::<Vec<_>>();
+        let advanced = self
+            .sender
+            .send(|attempt| {
+                let ixs = std::iter::once(ComputeBudgetInstruction::set_compute_unit_price(attempt.priority_fee))
+                    .chain(ixs.iter().cloned())
+                    .collect::<Vec<_>>();
+                let v0_msg = compile_v0_message(owner, &ixs, &[], attempt.blockhash)?;
+                Ok(VersionedTransaction::try_new(VersionedMessage::V0(v0_msg), &vec![keypair])?)
+            })
+            .await;
+        match advanced {
+            Ok(sent) if sent.outcome.is_landed() => {
+                log::warn!("advanced nonce {} past expired deposit {signature}", nonce.account);
+            }
+            Ok(sent) => {
+                log::error!("failed to advance nonce {} {:?}", nonce.account, sent.outcome);
+                return TxOutcome::Pending;
+            }
+            Err(err) => {
+                log::error!("failed to advance nonce {} {err:#?}", nonce.account);
+                return TxOutcome::Pending;
+            }
+        }
+        match self.sender.tracker().status(signature).await {
+            Ok(outcome) => outcome.unwrap_or(TxOutcome::Expired),
+            Err(err) => {
+                log::error!("failed to fetch the status of {signature} {err:#?}");
+                TxOutcome::Pending
+            }
+        }
+    }
+}
+
+/// simulates the add liquidity transaction instead of sending it, reporting the
+/// jlp that would be minted, the compute units consumed, the fee and any program error
+async fn simulate_deposit(
+    rpc: &RpcClient,
+    tx: &VersionedTransaction,
+    message: &solana_sdk::message::v0::Message,
+    owner: Pubkey,
+    lp_decimals: u8,
+) -> Result<()> {
+    let lp_ata = spl_associated_token_account::get_associated_token_address(&owner, &LP_TOKEN_MINT);
+    let pre_lp_balance = match rpc.get_account_data(&lp_ata).await {
+        Ok(acct_data) => spl_token::state::Account::unpack(&acct_data)?.amount,
+        // the ata is created by the simulated transaction
+        Err(_) => 0,
+    };
+    let fee = rpc.get_fee_for_message(message).await?;
+    let sim = rpc
+        .simulate_transaction_with_config(
+            tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                accounts: Some(RpcSimulateTransactionAccountsConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    addresses: vec![lp_ata.to_string()],
+                }),
+                ..Default::default()
+            },
+        )
+        .await
+        .with_context(|| "failed to simulate add liquidity tx")?
+        .value;
+    for log_line in sim.logs.as_deref().unwrap_or_default() {
+        log::debug!("{log_line}");
+    }
+    let units_consumed = sim.units_consumed.unwrap_or_default();
+    let fee_sol = solana_sdk::native_token::lamports_to_sol(fee);
+    if let Some(err) = sim.err {
+        match perpetuals::program_error::ProgramError::from_transaction_error(&err) {
+            Some(program_err) => log::error!("dry run failed with program error {program_err}"),
+            None => log::error!("dry run failed {err:#?}"),
+        }
+        log::info!("compute units consumed {units_consumed}, fee {fee_sol} sol ({fee} lamports)");
+        return Ok(());
+    }
+    let post_lp_balance = sim
+        .accounts
+        .and_then(|accounts| accounts.into_iter().next().flatten())
+        .and_then(|ui_acct| ui_acct.decode::<Account>())
+        .and_then(|acct| spl_token::state::Account::unpack(&acct.data).ok())
+        .map(|tkn_acct| tkn_acct.amount)
+        .with_context(|| "simulation did not return the jlp token account")?;
+    log::info!(
+        "dry run succeeded, expected jlp minted {}, compute units consumed {units_consumed}, fee {fee_sol} sol ({fee} lamports)",
+        spl_token::amount_to_ui_amount(post_lp_balance.saturating_sub(pre_lp_balance), lp_decimals),
+    );
+    Ok(())
+}
+
+/// closes the swap channel and waits for the swap task to handle every pending deposit
+async fn wait_for_swaps(
+    swap_tx: tokio::sync::mpsc::Sender<DepositLanded>,
+    swap_task: Option<tokio::task::JoinHandle<()>>,
+) {
+    // the swap task exits once every pending deposit has been reported
+    drop(swap_tx);
+    if let Some(swap_task) = swap_task {
+        let _ = swap_task.await;
+    }
+}
+
+/// records the final state of a sent deposit, notifying the swap task with
+/// the jlp actually minted when it landed, or `min_lp_out` when that can't be read
+async fn report_deposit(
+    tracker: ConfirmationTracker,
+    ledger: Arc<Ledger>,
+    owner: Pubkey,
+    sent: Sent,
+    min_lp_out: u64,
+    swap_tx: tokio::sync::mpsc::Sender<DepositLanded>,
+) {
+    let signature = sent.signature;
+    let (status, slot, lp_amount, fee_bps, error) = match sent.outcome {
+        TxOutcome::Landed { slot } => {
+            let (lp_amount, fee_bps) = match tracker.landed_transaction(&signature).await {
+                Ok(tx) => deposit_result(&tx, &owner),
                 Err(err) => {
-                    log::error!("failed to fetch quote {err:#?}, retrying... ");
-                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                    log::error!("failed to fetch jlp minted by {signature} {err:#?}");
+                    (None, None)
                 }
+            };
+            log::info!(
+                "add liquidity {signature} landed in slot {slot}, minted {lp_amount:?} jlp, fee {fee_bps:?} bps"
+            );
+            ("landed", Some(slot), lp_amount, fee_bps, None)
+        }
+        TxOutcome::Failed { slot, err } => {
+            let err = match perpetuals::program_error::ProgramError::from_transaction_error(&err) {
+                Some(program_err) => program_err.to_string(),
+                None => err.to_string(),
+            };
+            log::error!("add liquidity {signature} failed in slot {slot} {err}");
+            ("failed", Some(slot), None, None, Some(err))
+        }
+        TxOutcome::Rejected { err } => {
+            let err = match perpetuals::program_error::ProgramError::from_transaction_error(&err) {
+                Some(program_err) => program_err.to_string(),
+                None => err.to_string(),
+            };
+            log::error!("add liquidity {signature} rejected by preflight {err}");
+            ("rejected", None, None, None, Some(err))
+        }
+        TxOutcome::Expired => {
+            log::warn!("add liquidity {signature} expired after {} attempts", sent.attempts);
+            ("expired", None, None, None, None)
+        }
+        TxOutcome::Pending => {
+            log::warn!("add liquidity {signature} stopped being tracked before landing, it may yet land");
+            ("pending", None, None, None, None)
+        }
+    };
+    ledger.record(
+        &owner.to_string(),
+        LedgerEvent::DepositOutcome {
+            signature: signature.to_string(),
+            status: status.to_string(),
+            slot,
+            lp_amount,
+            fee_bps,
+            error,
+        },
+    );
+    if status == "landed" {
+        // the program guarantees at least min_lp_out was minted when the actual amount is unknown
+        let lp_amount = lp_amount.unwrap_or(min_lp_out);
+        if let Err(err) = swap_tx.send(DepositLanded { signature, lp_amount }).await {
+            log::error!("failed to send swap notification {err:#?}");
+        }
+    }
+}
+
+/// the jlp minted by a landed deposit and the fee it paid, read from the AddLiquidityEvent
+/// the program emitted, falling back to the owner's jlp balance change when there is none
+fn deposit_result(tx: &EncodedTransactionWithStatusMeta, owner: &Pubkey) -> (Option<u64>, Option<u64>) {
+    match perpetuals::event_parser::parse_transaction(tx) {
+        Ok(events) => {
+            if let Some(event) = events.into_iter().find_map(|event| match event {
+                PerpetualsEvent::AddLiquidity(event) => Some(event),
+                _ => None,
+            }) {
+                return (Some(event.lp_amount), Some(event.fee_bps));
             }
+            log::warn!("no add liquidity event found, using the jlp balance change");
+        }
+        Err(err) => log::warn!("failed to parse deposit events {err:#?}, using the jlp balance change"),
+    }
+    match tx_sender::confirmation::token_balance_change(tx, owner, &LP_TOKEN_MINT) {
+        Ok(delta) => (Some(u64::try_from(delta).unwrap_or_default()), None),
+        Err(err) => {
+            log::error!("failed to read jlp minted {err:#?}");
+            (None, None)
         }
-        log::error!("quote retry failed");
+    }
+}
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_turns() {
+        let turns = Turns::new(3);
+        assert!(turns.is_turn(0));
+        // only the wallet holding the turn can pass it
+        turns.pass(1);
+        assert!(turns.is_turn(0));
+        turns.pass(0);
+        assert!(turns.is_turn(1));
+        turns.stop(2);
+        turns.pass(1);
+        assert!(turns.is_turn(0));
+        turns.stop(0);
+        assert!(turns.is_turn(1));
+        turns.pass(1);
+        assert!(turns.is_turn(1));
+        assert_eq!(turns.active(), 1);
     }
-}
\ No newline at end of file
+}
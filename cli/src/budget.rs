@@ -0,0 +1,128 @@
+//! hard limits on how much auto-deposit may deposit in a single run
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// what the budget currently allows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetStatus {
+    /// a deposit of up to this usd amount (scaled by USD_DECIMALS) is allowed
+    Available(u128),
+    /// the rolling window cap is used up until the given instant
+    Paused(Instant),
+    /// a run-wide limit was reached, no further deposits are allowed
+    Exhausted(String),
+}
+
+/// tracks deposits made during a run against a total usd cap, a rolling window usd cap
+/// and a maximum number of deposits. usd amounts are scaled by USD_DECIMALS
+#[derive(Debug, Clone, Default)]
+pub struct DepositBudget {
+    max_total_usd: Option<u128>,
+    max_window_usd: Option<(Duration, u128)>,
+    max_deposits: Option<usize>,
+    total_usd: u128,
+    deposits: usize,
+    window: VecDeque<(Instant, u128)>,
+}
+
+impl DepositBudget {
+    pub fn new(
+        max_total_usd: Option<u128>,
+        max_window_usd: Option<(Duration, u128)>,
+        max_deposits: Option<usize>,
+    ) -> Self {
+        Self {
+            max_total_usd,
+            max_window_usd,
+            max_deposits,
+            ..Default::default()
+        }
+    }
+    /// returns how much may be deposited at `now`
+    pub fn status(&mut self, now: Instant) -> BudgetStatus {
+        if let Some(max_deposits) = self.max_deposits {
+            if self.deposits >= max_deposits {
+                return BudgetStatus::Exhausted(format!("reached max deposits {max_deposits}"));
+            }
+        }
+        let mut available = u128::MAX;
+        if let Some(max_total_usd) = self.max_total_usd {
+            if self.total_usd >= max_total_usd {
+                return BudgetStatus::Exhausted(format!(
+                    "deposited {} of max total usd {max_total_usd}",
+                    self.total_usd
+                ));
+            }
+            available = max_total_usd - self.total_usd;
+        }
+        if let Some((window, max_window_usd)) = self.max_window_usd {
+            while let Some((at, _)) = self.window.front() {
+                if now.saturating_duration_since(*at) < window {
+                    break;
+                }
+                self.window.pop_front();
+            }
+            let window_usd: u128 = self.window.iter().map(|(_, usd)| usd).sum();
+            if window_usd >= max_window_usd {
+                // the oldest deposit in the window is the next to free up room
+                let resumes_at = self
+                    .window
+                    .front()
+                    .map(|(at, _)| *at + window)
+                    .unwrap_or(now);
+                return BudgetStatus::Paused(resumes_at);
+            }
+            available = available.min(max_window_usd - window_usd);
+        }
+        BudgetStatus::Available(available)
+    }
+    /// records a deposit of `usd_amount` made at `now`
+    pub fn record(&mut self, now: Instant, usd_amount: u128) {
+        self.total_usd = self.total_usd.saturating_add(usd_amount);
+        self.deposits += 1;
+        if self.max_window_usd.is_some() {
+            self.window.push_back((now, usd_amount));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_deposit_budget() {
+        let now = Instant::now();
+        let mut budget = DepositBudget::default();
+        assert_eq!(budget.status(now), BudgetStatus::Available(u128::MAX));
+
+        let mut budget = DepositBudget::new(Some(100), None, Some(2));
+        budget.record(now, 60);
+        assert_eq!(budget.status(now), BudgetStatus::Available(40));
+        budget.record(now, 10);
+        assert!(matches!(budget.status(now), BudgetStatus::Exhausted(_)));
+
+        let mut budget = DepositBudget::new(Some(100), None, None);
+        budget.record(now, 100);
+        assert!(matches!(budget.status(now), BudgetStatus::Exhausted(_)));
+
+        let window = Duration::from_secs(60);
+        let mut budget = DepositBudget::new(Some(1_000), Some((window, 50)), None);
+        budget.record(now, 30);
+        assert_eq!(budget.status(now), BudgetStatus::Available(20));
+        budget.record(now + Duration::from_secs(10), 20);
+        assert_eq!(
+            budget.status(now + Duration::from_secs(20)),
+            BudgetStatus::Paused(now + window)
+        );
+        // the first deposit leaves the window
+        assert_eq!(
+            budget.status(now + window),
+            BudgetStatus::Available(30)
+        );
+        assert_eq!(
+            budget.status(now + window + Duration::from_secs(10)),
+            BudgetStatus::Available(50)
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! watches the pool for governance raising its aum cap, recording every opening and how fast
+//! it was filled in the ledger so wallets can be funded ahead of the next one
+
+use crate::check_jlp_liquidity::POOL_ACCT;
+use crate::ledger::{Ledger, LedgerEvent};
+use crate::pool_watcher::{PoolUpdate, PoolWatcher};
+use anyhow::Result;
+use config::Configuration;
+use perpetuals::capacity::{
+    CapacityEvent, CapacityOpening, CapacitySample, CapacityTracker, FillSummary,
+};
+use perpetuals::jlp_cacher::usd_to_ui;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// number of pool samples kept in memory
+const MAX_HISTORY: usize = 10_000;
+
+pub struct CapacityWatcher {
+    tracker: CapacityTracker,
+    ledger: Arc<Ledger>,
+    pool: String,
+}
+
+impl CapacityWatcher {
+    pub fn new(ledger: Arc<Ledger>, pool: Pubkey) -> Self {
+        Self {
+            tracker: CapacityTracker::new(MAX_HISTORY),
+            ledger,
+            pool: pool.to_string(),
+        }
+    }
+    /// tracks the pool update, logging and recording in the ledger any cap raise or
+    /// filled opening it reveals
+    pub fn observe(&mut self, update: &PoolUpdate) -> Vec<CapacityEvent> {
+        let sample = CapacitySample::from_pool(update.slot, unix_timestamp(), &update.pool);
+        let events = self.tracker.record(sample);
+        for event in &events {
+            match event {
+                CapacityEvent::Raised(opening) => {
+                    log::warn!(
+                        "aum cap raised at slot {} from {:.2} to {:.2} usd, opening {:.2} usd of room",
+                        opening.slot,
+                        usd_to_ui(opening.previous_max_aum_usd),
+                        usd_to_ui(opening.max_aum_usd),
+                        usd_to_ui(opening.room_usd),
+                    );
+                    self.ledger.record(
+                        &self.pool,
+                        LedgerEvent::CapacityRaised {
+                            slot: opening.slot,
+                            previous_max_aum_usd: saturating_u64(opening.previous_max_aum_usd),
+                            max_aum_usd: saturating_u64(opening.max_aum_usd),
+                            room_usd: saturating_u64(opening.room_usd),
+                        },
+                    );
+                }
+                CapacityEvent::Filled(opening) => {
+                    let (slot, _) = opening.filled.unwrap_or_default();
+                    let fill_secs = opening.fill_secs().unwrap_or_default();
+                    log::info!(
+                        "room opened at slot {} filled at slot {slot}, {fill_secs}s after the cap was raised",
+                        opening.slot,
+                    );
+                    self.ledger.record(
+                        &self.pool,
+                        LedgerEvent::CapacityFilled {
+                            raised_slot: opening.slot,
+                            slot,
+                            fill_secs,
+                        },
+                    );
+                }
+            }
+        }
+        events
+    }
+    /// observes every pool update in the background until the pool watcher stops
+    pub fn spawn(
+        mut self,
+        mut pool_rx: watch::Receiver<Option<PoolUpdate>>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::task::spawn(async move {
+            while pool_rx.changed().await.is_ok() {
+                let update = pool_rx.borrow_and_update().clone();
+                if let Some(update) = update {
+                    self.observe(&update);
+                }
+            }
+        })
+    }
+}
+
+/// rebuilds past openings of the pool from the cap raises and fills recorded in the ledger,
+/// oldest first. raises recorded by several processes watching at once are only counted once
+pub fn openings(ledger: &Ledger, pool: &str) -> Result<Vec<CapacityOpening>> {
+    let mut openings = BTreeMap::new();
+    for entry in ledger.entries()? {
+        if entry.owner != pool {
+            continue;
+        }
+        match entry.event {
+            LedgerEvent::CapacityRaised {
+                slot,
+                previous_max_aum_usd,
+                max_aum_usd,
+                room_usd,
+            } => {
+                openings.entry(slot).or_insert(CapacityOpening {
+                    slot,
+                    timestamp: entry.timestamp as i64,
+                    previous_max_aum_usd: previous_max_aum_usd as u128,
+                    max_aum_usd: max_aum_usd as u128,
+                    room_usd: room_usd as u128,
+                    // the ledger only records the room added by the raise
+                    total_room_usd: room_usd as u128,
+                    filled: None,
+                });
+            }
+            LedgerEvent::CapacityFilled {
+                raised_slot,
+                slot,
+                fill_secs,
+            } => {
+                if let Some(opening) = openings.get_mut(&raised_slot) {
+                    opening.filled.get_or_insert((slot, opening.timestamp + fill_secs));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(openings.into_values().collect())
+}
+
+/// monitors the pool's aum cap without depositing, reporting raises as they happen along
+/// with how fast past openings were filled
+pub async fn watch(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let rpc = Arc::new(conf.rpc());
+    let ledger = Arc::new(Ledger::new(conf.ledger_path()));
+    let summary_interval = *matches.get_one::<u64>("summary-interval").unwrap();
+
+    let pool = Pubkey::from_str(POOL_ACCT).unwrap();
+    let mut pool_rx = PoolWatcher::new(
+        rpc,
+        conf.ws_url(),
+        pool,
+        std::time::Duration::from_millis(250),
+    )
+    .spawn();
+    let mut watcher = CapacityWatcher::new(ledger.clone(), pool);
+    let report = |ledger: &Ledger| match openings(ledger, POOL_ACCT) {
+        Ok(openings) => log::info!("past openings {}", FillSummary::new(&openings)),
+        Err(err) => log::error!("failed to read past openings {err:#?}"),
+    };
+    report(&ledger);
+
+    let mut ticker =
+        tokio::time::interval(std::time::Duration::from_secs(summary_interval.max(1)));
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            changed = pool_rx.changed() => {
+                if changed.is_err() {
+                    return Err(anyhow::anyhow!("pool watcher stopped"));
+                }
+                let update = pool_rx.borrow_and_update().clone();
+                if let Some(update) = update {
+                    let events = watcher.observe(&update);
+                    if events.iter().any(|event| matches!(event, CapacityEvent::Filled(_))) {
+                        report(&ledger);
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                match pool_rx.borrow().as_ref() {
+                    Some(update) => log::info!(
+                        "slot {} aum {:.2} usd, cap {:.2} usd, room {:.2} usd",
+                        update.slot,
+                        usd_to_ui(update.pool.aum_usd),
+                        usd_to_ui(update.pool.limit.max_aum_usd),
+                        usd_to_ui(update.pool.limit.max_aum_usd.saturating_sub(update.pool.aum_usd)),
+                    ),
+                    None => log::info!("waiting for the first pool update"),
+                }
+                report(&ledger);
+            }
+            _ = tokio::signal::ctrl_c() => {
+                log::info!("goodbye");
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn unix_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+fn saturating_u64(amount: u128) -> u64 {
+    amount.min(u64::MAX as u128) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_openings() {
+        let _ = std::fs::remove_file("test_capacity_ledger.jsonl");
+        let ledger = Ledger::new("test_capacity_ledger.jsonl");
+        let raised = |slot| LedgerEvent::CapacityRaised {
+            slot,
+            previous_max_aum_usd: 1_000,
+            max_aum_usd: 2_000,
+            room_usd: 1_000,
+        };
+        ledger.record(POOL_ACCT, raised(10));
+        // recorded again by a second process watching the same pool
+        ledger.record(POOL_ACCT, raised(10));
+        ledger.record("other", raised(11));
+        ledger.record(POOL_ACCT, raised(20));
+        ledger.record(
+            POOL_ACCT,
+            LedgerEvent::CapacityFilled {
+                raised_slot: 10,
+                slot: 15,
+                fill_secs: 3,
+            },
+        );
+        let openings = openings(&ledger, POOL_ACCT).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].fill_slots(), Some(5));
+        assert_eq!(openings[0].fill_secs(), Some(3));
+        assert_eq!(openings[1].slot, 20);
+        assert!(openings[1].filled.is_none());
+        std::fs::remove_file("test_capacity_ledger.jsonl").unwrap();
+    }
+}
@@ -1,11 +1,169 @@
+//! reports the pool's capacity and the composition and utilization of every custody, so the
+//! token furthest below its target weight can be picked for deposits
+
 use anchor_lang::prelude::*;
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use config::Configuration;
+use perpetuals::aum::{assets_under_management_usd, custody_aum_usd};
+use perpetuals::jlp_cacher::{token_to_usd_amount, usd_to_ui, BPS_POWER};
+use perpetuals::PriceCalcMode;
+use serde::Serialize;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::Signer;
 use std::str::FromStr;
 
 pub const PERPETUALS_ACCT: &str = "H4ND9aYttUVLFmNypZqLjZ52FYiGvdEB45GmwNoKEjTj";
 pub const POOL_ACCT: &str = "5BUwFW4nRbftYTDMbgxykoFWqWHPzahFSNAaaaJtVKsq";
+/// difference between the recomputed and simulated aum tolerated for oracle updates landing
+/// between the two reads
+const AUM_TOLERANCE_BPS: f64 = 1.0;
+
+/// the custody fields the report is computed from. token amounts are in the custody token's
+/// smallest unit, usd amounts and prices are scaled by USD_DECIMALS
+struct CustodyAmounts {
+    decimals: u8,
+    is_stable: bool,
+    target_ratio_bps: u64,
+    owned: u64,
+    locked: u64,
+    guaranteed_usd: u64,
+    global_short_sizes: u64,
+    global_short_average_price: u64,
+    /// pyth price of the custody token
+    price: u64,
+    /// the custody's share of the pool's value
+    value_usd: u128,
+}
+
+impl CustodyAmounts {
+    fn new(custody: &perpetuals::Custody, price: u64) -> Self {
+        Self {
+            decimals: custody.decimals,
+            is_stable: custody.is_stable,
+            target_ratio_bps: custody.target_ratio_bps,
+            owned: custody.assets.owned,
+            locked: custody.assets.locked,
+            guaranteed_usd: custody.assets.guaranteed_usd,
+            global_short_sizes: custody.assets.global_short_sizes,
+            global_short_average_price: custody.assets.global_short_average_prices,
+            price,
+            value_usd: custody_aum_usd(custody, price).max(0) as u128,
+        }
+    }
+    fn owned_usd(&self) -> u128 {
+        token_to_usd_amount(self.owned, self.price, self.decimals)
+    }
+    fn locked_usd(&self) -> u128 {
+        token_to_usd_amount(self.locked, self.price, self.decimals)
+    }
+}
+
+/// usd amounts are in dollars and token amounts in ui units
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CustodyReport {
+    pub mint: String,
+    pub custody: String,
+    pub is_stable: bool,
+    pub price: f64,
+    pub owned: f64,
+    pub locked: f64,
+    pub owned_usd: f64,
+    pub locked_usd: f64,
+    pub guaranteed_usd: f64,
+    /// share of owned tokens locked by open positions
+    pub utilization_pct: f64,
+    pub value_usd: f64,
+    pub target_weight_pct: f64,
+    pub weight_pct: f64,
+    pub global_short_sizes_usd: f64,
+    pub global_short_average_price: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PoolReport {
+    pub pool: String,
+    /// as stored by the pool, which is only updated when the pool is refreshed
+    pub aum_usd: f64,
+    /// recomputed from the custodies and their oracles
+    pub aum_min_usd: f64,
+    pub aum_max_usd: f64,
+    pub max_aum_usd: f64,
+    pub room_usd: f64,
+    pub custodies: Vec<CustodyReport>,
+}
+
+impl PoolReport {
+    fn new(
+        pool: Pubkey,
+        aum_usd: u128,
+        (aum_min_usd, aum_max_usd): (u128, u128),
+        max_aum_usd: u128,
+        custodies: Vec<(Pubkey, Pubkey, CustodyAmounts)>,
+    ) -> Self {
+        let total_value_usd: u128 = custodies.iter().map(|(_, _, amounts)| amounts.value_usd).sum();
+        let pct = |part: u128, whole: u128| {
+            if whole == 0 {
+                0.0
+            } else {
+                part as f64 * 100.0 / whole as f64
+            }
+        };
+        let ui = |amount: u64, decimals: u8| spl_token::amount_to_ui_amount(amount, decimals);
+        Self {
+            pool: pool.to_string(),
+            aum_usd: usd_to_ui(aum_usd),
+            aum_min_usd: usd_to_ui(aum_min_usd),
+            aum_max_usd: usd_to_ui(aum_max_usd),
+            max_aum_usd: usd_to_ui(max_aum_usd),
+            room_usd: usd_to_ui(max_aum_usd.saturating_sub(aum_usd)),
+            custodies: custodies
+                .into_iter()
+                .map(|(mint, custody, amounts)| CustodyReport {
+                    mint: mint.to_string(),
+                    custody: custody.to_string(),
+                    is_stable: amounts.is_stable,
+                    price: usd_to_ui(amounts.price as u128),
+                    owned: ui(amounts.owned, amounts.decimals),
+                    locked: ui(amounts.locked, amounts.decimals),
+                    owned_usd: usd_to_ui(amounts.owned_usd()),
+                    locked_usd: usd_to_ui(amounts.locked_usd()),
+                    guaranteed_usd: usd_to_ui(amounts.guaranteed_usd as u128),
+                    utilization_pct: pct(amounts.locked as u128, amounts.owned as u128),
+                    value_usd: usd_to_ui(amounts.value_usd),
+                    target_weight_pct: pct(amounts.target_ratio_bps as u128, BPS_POWER as u128),
+                    weight_pct: pct(amounts.value_usd, total_value_usd),
+                    global_short_sizes_usd: usd_to_ui(amounts.global_short_sizes as u128),
+                    global_short_average_price: usd_to_ui(amounts.global_short_average_price as u128),
+                })
+                .collect(),
+        }
+    }
+    fn print_table(&self) {
+        println!(
+            "pool {} aum {:.2} usd (recomputed {:.2} - {:.2} usd), cap {:.2} usd, room {:.2} usd",
+            self.pool, self.aum_usd, self.aum_min_usd, self.aum_max_usd, self.max_aum_usd, self.room_usd
+        );
+        println!(
+            "{:<44} {:>12} {:>16} {:>16} {:>16} {:>7} {:>7} {:>7} {:>16} {:>12}",
+            "mint", "price", "owned_usd", "locked_usd", "guaranteed_usd", "util%", "target%", "weight%", "shorts_usd", "short_price"
+        );
+        for custody in &self.custodies {
+            println!(
+                "{:<44} {:>12.4} {:>16.2} {:>16.2} {:>16.2} {:>7.2} {:>7.2} {:>7.2} {:>16.2} {:>12.4}",
+                custody.mint,
+                custody.price,
+                custody.owned_usd,
+                custody.locked_usd,
+                custody.guaranteed_usd,
+                custody.utilization_pct,
+                custody.target_weight_pct,
+                custody.weight_pct,
+                custody.global_short_sizes_usd,
+                custody.global_short_average_price,
+            );
+        }
+    }
+}
 
 pub async fn check_jlp_liquidity(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
     let conf = Configuration::load(conf_path)?;
@@ -15,8 +173,129 @@ pub async fn check_jlp_liquidity(matches: &clap::ArgMatches, conf_path: &str) ->
     let perp = Pubkey::from_str(PERPETUALS_ACCT).unwrap();
     let jlp_cache_accounts =
         perpetuals::jlp_cacher::JLPCacheAccountKeys::load_account_keys(&rpc, perp, pool).await?;
+    let pool_acct = perpetuals::Pool::deserialize(&mut &rpc.get_account_data(&pool).await?[8..])?;
+    let custodies = jlp_cache_accounts.load_custodies(&rpc).await?;
+    let prices = jlp_cache_accounts.oracle_prices(&rpc, &custodies).await?;
 
-    log::info!("{:#?}", jlp_cache_accounts);
+    let mut amounts = Vec::with_capacity(custodies.len());
+    for ((keys, custody), price) in jlp_cache_accounts
+        .custody_accounts
+        .iter()
+        .zip(&custodies)
+        .zip(&prices)
+    {
+        amounts.push((keys.mint, keys.account, CustodyAmounts::new(custody, price.price.usd()?)));
+    }
+    if amounts.is_empty() {
+        return Err(anyhow!("pool {pool} has no custodies"));
+    }
+    let computed_aum = |mode| assets_under_management_usd(&custodies, &prices, &mode);
+    let report = PoolReport::new(
+        pool,
+        pool_acct.aum_usd,
+        (computed_aum(PriceCalcMode::Min)?, computed_aum(PriceCalcMode::Max)?),
+        pool_acct.limit.max_aum_usd,
+        amounts,
+    );
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.print_table();
+    }
+
+    if matches.get_flag("verify-aum") {
+        // only pays for the simulated views
+        let payer = conf.keypair.keypair()?.pubkey();
+        for (name, mode) in [
+            ("min", PriceCalcMode::Min),
+            ("max", PriceCalcMode::Max),
+            ("ignore", PriceCalcMode::Ignore),
+        ] {
+            let computed = computed_aum(mode.clone())?;
+            let simulated = jlp_cache_accounts
+                .assets_under_management(&rpc, payer, Some(mode))
+                .await?;
+            let diff_bps = computed.abs_diff(simulated) as f64 * BPS_POWER as f64 / simulated.max(1) as f64;
+            if diff_bps > AUM_TOLERANCE_BPS {
+                log::warn!(
+                    "{name} aum recomputed as {:.2} usd but the program values it at {:.2} usd, off by {diff_bps:.2} bps",
+                    usd_to_ui(computed),
+                    usd_to_ui(simulated),
+                );
+            } else {
+                log::info!(
+                    "{name} aum recomputed as {:.2} usd, the program values it at {:.2} usd",
+                    usd_to_ui(computed),
+                    usd_to_ui(simulated),
+                );
+            }
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_pool_report() {
+        let (sol, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let report = PoolReport::new(
+            Pubkey::new_unique(),
+            2_000_000_000,
+            (1_990_000_000, 2_010_000_000),
+            2_500_000_000,
+            vec![
+                (
+                    sol,
+                    Pubkey::new_unique(),
+                    CustodyAmounts {
+                        decimals: 9,
+                        is_stable: false,
+                        target_ratio_bps: 5_000,
+                        // 10 sol at $100, 4 of which are locked by longs backed by $300
+                        owned: 10_000_000_000,
+                        locked: 4_000_000_000,
+                        guaranteed_usd: 300_000_000,
+                        global_short_sizes: 50_000_000,
+                        global_short_average_price: 95_000_000,
+                        price: 100_000_000,
+                        value_usd: 900_000_000,
+                    },
+                ),
+                (
+                    usdc,
+                    Pubkey::new_unique(),
+                    CustodyAmounts {
+                        decimals: 6,
+                        is_stable: true,
+                        target_ratio_bps: 5_000,
+                        owned: 1_100_000_000,
+                        locked: 275_000_000,
+                        guaranteed_usd: 0,
+                        global_short_sizes: 0,
+                        global_short_average_price: 0,
+                        price: 1_000_000,
+                        value_usd: 1_100_000_000,
+                    },
+                ),
+            ],
+        );
+        assert_eq!(report.room_usd, 500.0);
+        assert_eq!(report.aum_max_usd, 2_010.0);
+        let sol_report = &report.custodies[0];
+        assert_eq!(sol_report.mint, sol.to_string());
+        assert_eq!(sol_report.owned_usd, 1_000.0);
+        assert_eq!(sol_report.locked_usd, 400.0);
+        assert_eq!(sol_report.utilization_pct, 40.0);
+        assert_eq!(sol_report.value_usd, 900.0);
+        assert_eq!(sol_report.weight_pct, 45.0);
+        assert_eq!(sol_report.target_weight_pct, 50.0);
+        assert_eq!(sol_report.global_short_sizes_usd, 50.0);
+        let usdc_report = &report.custodies[1];
+        assert_eq!(usdc_report.value_usd, 1_100.0);
+        assert_eq!(usdc_report.utilization_pct, 25.0);
+        assert_eq!(usdc_report.weight_pct, 55.0);
+    }
+}
@@ -0,0 +1,299 @@
+//! an append-only jsonl ledger recording every deposit and swap the cli sends, used to
+//! reconcile wallet balances after the fact
+
+use anyhow::{Context, Result};
+use config::Configuration;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    /// unix timestamp in seconds at which the entry was recorded
+    pub timestamp: u64,
+    pub owner: String,
+    #[serde(flatten)]
+    pub event: LedgerEvent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LedgerEvent {
+    /// an add liquidity transaction was sent, or failed to send
+    Deposit {
+        deposit_mint: String,
+        amount_in: u64,
+        /// the non-custody token swapped into deposit_mint within the deposit transaction
+        pre_swap_mint: Option<String>,
+        pre_swap_amount: Option<u64>,
+        expected_lp_out: u64,
+        min_lp_out: u64,
+        jlp_price: f64,
+        /// pool aum scaled by USD_DECIMALS, as u128 does not round trip through tagged enums
+        pool_aum_usd: u64,
+        signature: Option<String>,
+        error: Option<String>,
+    },
+    /// the final state of a sent add liquidity transaction
+    DepositOutcome {
+        signature: String,
+        /// one of landed, failed, rejected, expired or pending
+        status: String,
+        slot: Option<u64>,
+        /// jlp actually minted to the owner, only known for landed deposits
+        lp_amount: Option<u64>,
+        /// fee charged by the program, read from the deposit's AddLiquidityEvent
+        fee_bps: Option<u64>,
+        error: Option<String>,
+    },
+    /// a jupiter swap of jlp back into another token was sent, or failed to send
+    SwapBack {
+        input_mint: String,
+        output_mint: String,
+        amount_in: u64,
+        expected_out: u64,
+        /// usdc actually received, verified from the landed transaction's balance changes
+        amount_out: Option<u64>,
+        signature: Option<String>,
+        error: Option<String>,
+    },
+    /// governance raised the pool's aum cap. usd values are scaled by USD_DECIMALS
+    CapacityRaised {
+        slot: u64,
+        previous_max_aum_usd: u64,
+        max_aum_usd: u64,
+        room_usd: u64,
+    },
+    /// the room opened by the cap raise at raised_slot was filled
+    CapacityFilled {
+        raised_slot: u64,
+        slot: u64,
+        fill_secs: i64,
+    },
+}
+
+impl LedgerEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Deposit { .. } => "deposit",
+            Self::DepositOutcome { .. } => "deposit_outcome",
+            Self::SwapBack { .. } => "swap_back",
+            Self::CapacityRaised { .. } => "capacity_raised",
+            Self::CapacityFilled { .. } => "capacity_filled",
+        }
+    }
+}
+
+pub struct Ledger {
+    path: PathBuf,
+    // serializes appends from concurrent tasks
+    lock: Mutex<()>,
+}
+
+impl Ledger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+    /// appends an event to the ledger, logging instead of failing so that
+    /// ledger issues never interrupt deposits
+    pub fn record(&self, owner: &str, event: LedgerEvent) {
+        let entry = LedgerEntry {
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            owner: owner.to_string(),
+            event,
+        };
+        if let Err(err) = self.append(&entry) {
+            log::error!("failed to record ledger entry {entry:?} {err:#?}");
+        }
+    }
+    fn append(&self, entry: &LedgerEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let _guard = self.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open ledger {}", self.path.display()))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+    /// reads every entry in the ledger, oldest first
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = vec![];
+        for (idx, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(
+                serde_json::from_str(&line)
+                    .with_context(|| format!("failed to decode ledger line {}", idx + 1))?,
+            );
+        }
+        Ok(entries)
+    }
+}
+
+/// prints the most recent ledger entries, optionally filtered by kind and owner
+pub async fn history(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let ledger = Ledger::new(conf.ledger_path());
+    let kind = matches.get_one::<String>("kind");
+    let owner = matches.get_one::<String>("owner");
+    let limit = *matches.get_one::<usize>("limit").unwrap();
+    let as_json = matches.get_flag("json");
+
+    let entries = ledger
+        .entries()?
+        .into_iter()
+        .filter(|entry| match kind {
+            Some(kind) => entry.event.kind() == kind,
+            None => true,
+        })
+        .filter(|entry| match owner {
+            Some(owner) => &entry.owner == owner,
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    let skip = entries.len().saturating_sub(limit);
+    for entry in &entries[skip..] {
+        if as_json {
+            println!("{}", serde_json::to_string(entry)?);
+            continue;
+        }
+        match &entry.event {
+            LedgerEvent::Deposit {
+                deposit_mint,
+                amount_in,
+                pre_swap_mint,
+                pre_swap_amount,
+                expected_lp_out,
+                min_lp_out,
+                jlp_price,
+                pool_aum_usd,
+                signature,
+                error,
+            } => println!(
+                "{} deposit owner={} mint={deposit_mint} amount_in={amount_in} pre_swap_mint={} pre_swap_amount={} expected_lp_out={expected_lp_out} min_lp_out={min_lp_out} jlp_price={jlp_price} pool_aum_usd={pool_aum_usd} signature={} error={}",
+                entry.timestamp,
+                entry.owner,
+                pre_swap_mint.as_deref().unwrap_or("-"),
+                pre_swap_amount.map(|amount| amount.to_string()).unwrap_or_else(|| "-".to_string()),
+                signature.as_deref().unwrap_or("-"),
+                error.as_deref().unwrap_or("-"),
+            ),
+            LedgerEvent::DepositOutcome {
+                signature,
+                status,
+                slot,
+                lp_amount,
+                fee_bps,
+                error,
+            } => println!(
+                "{} deposit_outcome owner={} signature={signature} status={status} slot={} lp_amount={} fee_bps={} error={}",
+                entry.timestamp,
+                entry.owner,
+                slot.map(|slot| slot.to_string()).unwrap_or_else(|| "-".to_string()),
+                lp_amount.map(|amount| amount.to_string()).unwrap_or_else(|| "-".to_string()),
+                fee_bps.map(|fee_bps| fee_bps.to_string()).unwrap_or_else(|| "-".to_string()),
+                error.as_deref().unwrap_or("-"),
+            ),
+            LedgerEvent::SwapBack {
+                input_mint,
+                output_mint,
+                amount_in,
+                expected_out,
+                amount_out,
+                signature,
+                error,
+            } => println!(
+                "{} swap_back owner={} input_mint={input_mint} output_mint={output_mint} amount_in={amount_in} expected_out={expected_out} amount_out={} signature={} error={}",
+                entry.timestamp,
+                entry.owner,
+                amount_out.map(|amount| amount.to_string()).unwrap_or_else(|| "-".to_string()),
+                signature.as_deref().unwrap_or("-"),
+                error.as_deref().unwrap_or("-"),
+            ),
+            LedgerEvent::CapacityRaised {
+                slot,
+                previous_max_aum_usd,
+                max_aum_usd,
+                room_usd,
+            } => println!(
+                "{} capacity_raised pool={} slot={slot} previous_max_aum_usd={previous_max_aum_usd} max_aum_usd={max_aum_usd} room_usd={room_usd}",
+                entry.timestamp, entry.owner,
+            ),
+            LedgerEvent::CapacityFilled {
+                raised_slot,
+                slot,
+                fill_secs,
+            } => println!(
+                "{} capacity_filled pool={} raised_slot={raised_slot} slot={slot} fill_secs={fill_secs}",
+                entry.timestamp, entry.owner,
+            ),
+        }
+    }
+    log::info!("showing {} of {} ledger entries", entries.len() - skip, entries.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_ledger() {
+        let _ = std::fs::remove_file("test_ledger.jsonl");
+        let ledger = Ledger::new("test_ledger.jsonl");
+        ledger.record(
+            "owner",
+            LedgerEvent::Deposit {
+                deposit_mint: "mint".to_string(),
+                amount_in: 100,
+                pre_swap_mint: None,
+                pre_swap_amount: None,
+                expected_lp_out: 50,
+                min_lp_out: 49,
+                jlp_price: 2.0,
+                pool_aum_usd: 1_000_000_000_000_000,
+                signature: Some("sig".to_string()),
+                error: None,
+            },
+        );
+        ledger.record(
+            "owner",
+            LedgerEvent::SwapBack {
+                input_mint: "jlp".to_string(),
+                output_mint: "usdc".to_string(),
+                amount_in: 50,
+                expected_out: 99,
+                amount_out: None,
+                signature: None,
+                error: Some("failed".to_string()),
+            },
+        );
+        let entries = ledger.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].event.kind(), "deposit");
+        assert_eq!(entries[1].event.kind(), "swap_back");
+        match &entries[0].event {
+            LedgerEvent::Deposit { pool_aum_usd, .. } => {
+                assert_eq!(*pool_aum_usd, 1_000_000_000_000_000)
+            }
+            _ => panic!("unexpected event"),
+        }
+        std::fs::remove_file("test_ledger.jsonl").unwrap();
+    }
+}
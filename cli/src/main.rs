@@ -8,7 +8,16 @@ use {
 };
 
 mod auto_depositor;
+mod budget;
+mod capacity;
 mod check_jlp_liquidity;
+mod ledger;
+mod nonce;
+mod pool_watcher;
+mod pre_swap;
+mod quote_deposit;
+mod remove_liquidity;
+mod swap_back;
 mod swapper;
 
 
@@ -24,12 +33,58 @@ pub async fn main() -> Result<()> {
                     .aliases(["gen", "generate"])
                     .about("create and save a new configuration file")
                     .arg(keypair_type_flag())]),
-            Command::new("check-jlp-liquidity"),
+            Command::new("check-jlp-liquidity")
+                .about("report the pool's capacity along with every custody's value, utilization and weight")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("print the report as json")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("verify-aum")
+                        .long("verify-aum")
+                        .help("cross-check the recomputed aum against the program's getAssetsUnderManagement")
+                        .long_help("simulates getAssetsUnderManagement under every price mode, paid for by the configured keypair, and warns when the aum recomputed from the custodies and oracles differs from it")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                ),
+            Command::new("quote-deposit")
+                .about("rank custodies by the fee predicted for depositing a usd amount through each")
+                .arg(
+                    Arg::new("amount-usd")
+                        .long("amount-usd")
+                        .help("usd amount (ie: 1000.5) to quote")
+                        .value_parser(clap::value_parser!(f64))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("print the quotes as json")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .help("cross-check the predicted fees against the program's getAddLiquidityAmountAndFee")
+                        .long_help("simulates getAddLiquidityAmountAndFee for every custody, paid for by the configured keypair, and warns when the predicted deposit fee differs from it")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                ),
             Command::new("auto-deposit")
                 .arg(
                     Arg::new("deposit-mint")
                         .long("deposit-mint")
-                        .help("token mint to deposit, only usdc supported"),
+                        .help("token mint to deposit, any jlp custody token (sol, eth, btc, usdc, usdt) or any token jupiter can swap into one"),
+                )
+                .arg(
+                    Arg::new("custody-mint")
+                        .long("custody-mint")
+                        .help("custody token a non-custody deposit mint is swapped into within the deposit transaction")
+                        .default_value("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
                 )
                 .arg(
                     Arg::new("deposit-amount")
@@ -52,12 +107,193 @@ pub async fn main() -> Result<()> {
                         .action(clap::ArgAction::SetTrue)
                         .required(false),
                 )
+                .arg(
+                    Arg::new("priority-fee-percentile")
+                        .long("priority-fee-percentile")
+                        .help("percentile (0-100) of recent priority fees paid for the pool and custody accounts to pay, overriding the config")
+                        .value_parser(clap::value_parser!(u8).range(0..=100)),
+                )
+                .arg(
+                    Arg::new("min-priority-fee")
+                        .long("min-priority-fee")
+                        .help("lowest compute unit price to pay in micro-lamports, overriding the config")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("max-priority-fee")
+                        .long("max-priority-fee")
+                        .help("highest compute unit price to pay in micro-lamports, overriding the config")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("compute-unit-margin-bps")
+                        .long("compute-unit-margin-bps")
+                        .help("margin in bps added to the compute units a deposit consumes in simulation, overriding the config")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("slippage-bps")
+                        .long("slippage-bps")
+                        .help("slippage tolerance in bps applied to the jlp amount quoted by the program")
+                        .default_value("100")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("max-total-usd")
+                        .long("max-total-usd")
+                        .help("stop once this much usd (ie: 1000.5) has been deposited during the run")
+                        .value_parser(clap::value_parser!(f64)),
+                )
+                .arg(
+                    Arg::new("max-window-usd")
+                        .long("max-window-usd")
+                        .help("pause once this much usd has been deposited within the rolling budget window")
+                        .value_parser(clap::value_parser!(f64)),
+                )
+                .arg(
+                    Arg::new("budget-window-secs")
+                        .long("budget-window-secs")
+                        .help("length in seconds of the rolling window used by --max-window-usd")
+                        .default_value("3600")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("max-deposits")
+                        .long("max-deposits")
+                        .help("stop after this many deposits have landed")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("swap-back")
+                        .long("swap-back")
+                        .help("how much jlp to swap back to usdc after each deposit, overriding the config")
+                        .long_help("one of hold, minted (only jlp minted by this run), percent:<0-100> (of the jlp balance) or premium:<bps> (the balance, only when the market quote is at least bps above nav)"),
+                )
+                .arg(
+                    Arg::new("commitment")
+                        .long("commitment")
+                        .help("commitment a deposit must reach before jlp is swapped back (processed, confirmed, finalized)")
+                        .default_value("confirmed")
+                        .value_parser(["processed", "confirmed", "finalized"]),
+                )
+                .arg(
+                    Arg::new("wallet-mode")
+                        .long("wallet-mode")
+                        .help("how the configured wallets share a capacity opening")
+                        .long_help("split sizes every wallet's deposit against an equal share of the available room and deposits from all of them at once, round-robin gives each wallet the full room in turn")
+                        .default_value("split")
+                        .value_parser(["split", "round-robin"]),
+                )
+                .arg(
+                    Arg::new("durable-nonce")
+                        .long("durable-nonce")
+                        .help("pre-sign deposits against each wallet's nonce account and broadcast one as soon as capacity opens")
+                        .long_help("signs deposits of several sizes ahead of time against each wallet's durable nonce account (see the nonce subcommands), so no blockhash is fetched and nothing is signed once capacity opens. the largest pre-signed deposit that fits is broadcast, and the rest are discarded once the nonce advances. cannot be combined with depositing tokens that need a pre-swap")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("presign-percents")
+                        .long("presign-percents")
+                        .help("sizes to pre-sign deposits at, as percentages of the deposit amount")
+                        .default_value("100,50,25,10")
+                        .value_delimiter(',')
+                        .value_parser(clap::value_parser!(u8).range(1..=100)),
+                )
+                .arg(nonce_seed_flag())
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("simulate the deposit transaction instead of sending it")
+                        .long_help("builds the add liquidity transaction the deposit loop would send, simulates it, reports the expected jlp minted, compute units, fee and any program error, then exits")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                ),
+            Command::new("remove-liquidity")
+                .about("redeem jlp for one of the pool's custody tokens")
+                .arg(
+                    Arg::new("receive-mint")
+                        .long("receive-mint")
+                        .help("custody token mint to receive (sol, eth, btc, usdc, usdt)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("lp-amount")
+                        .long("lp-amount")
+                        .help("ui amount (ie: 1.5) of jlp to redeem, defaults to the entire balance")
+                        .value_parser(clap::value_parser!(f64)),
+                )
+                .arg(
+                    Arg::new("slippage-bps")
+                        .long("slippage-bps")
+                        .help("slippage tolerance in bps applied to the amount quoted by the program")
+                        .default_value("100")
+                        .value_parser(clap::value_parser!(u64)),
+                )
                 .arg(
                     Arg::new("priority-fee")
                         .long("priority-fee")
                         .help("priority fee to use (ie: 0.01)")
+                        .default_value("0.001")
                         .value_parser(clap::value_parser!(f64)),
                 ),
+            Command::new("nonce")
+                .about("manage the durable nonce accounts deposits are pre-signed against")
+                .subcommands(vec![
+                    Command::new("create")
+                        .about("create a nonce account for every configured wallet")
+                        .arg(nonce_seed_flag()),
+                    Command::new("advance")
+                        .about("advance every configured wallet's nonce, invalidating pre-signed deposits")
+                        .arg(nonce_seed_flag()),
+                    Command::new("close")
+                        .about("close every configured wallet's nonce account, returning its lamports")
+                        .arg(nonce_seed_flag()),
+                ]),
+            Command::new("capacity")
+                .about("monitor the pool's aum cap")
+                .subcommands(vec![Command::new("watch")
+                    .about("report raises of the aum cap as they happen and how fast past openings filled, without depositing")
+                    .arg(
+                        Arg::new("summary-interval")
+                            .long("summary-interval")
+                            .help("seconds between reports of the pool's room and past openings")
+                            .default_value("300")
+                            .value_parser(clap::value_parser!(u64)),
+                    )]),
+            Command::new("history")
+                .about("show deposits, swaps and capacity openings recorded in the ledger")
+                .arg(
+                    Arg::new("kind")
+                        .long("kind")
+                        .help("only show entries of this kind")
+                        .value_parser([
+                            "deposit",
+                            "deposit_outcome",
+                            "swap_back",
+                            "capacity_raised",
+                            "capacity_filled",
+                        ]),
+                )
+                .arg(
+                    Arg::new("owner")
+                        .long("owner")
+                        .help("only show entries for this wallet"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .help("maximum number of the most recent entries to show")
+                        .default_value("50")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("print entries as json lines")
+                        .action(clap::ArgAction::SetTrue)
+                        .required(false),
+                ),
                 Command::new("swap-tokens")
                 .arg(
                     Arg::new("input-token")
@@ -100,7 +336,22 @@ async fn process_matches(matches: &ArgMatches, conf_path: &str) -> Result<()> {
         Some(("check-jlp-liquidity", cjl)) => {
             Ok(check_jlp_liquidity::check_jlp_liquidity(cjl, conf_path).await?)
         }
+        Some(("quote-deposit", qd)) => Ok(quote_deposit::quote_deposit(qd, conf_path).await?),
         Some(("auto-deposit", ad)) => Ok(auto_depositor::auto_deposit(ad, conf_path).await?),
+        Some(("remove-liquidity", rl)) => {
+            Ok(remove_liquidity::remove_liquidity(rl, conf_path).await?)
+        }
+        Some(("nonce", n)) => match n.subcommand() {
+            Some(("create", c)) => Ok(nonce::create(c, conf_path).await?),
+            Some(("advance", a)) => Ok(nonce::advance(a, conf_path).await?),
+            Some(("close", c)) => Ok(nonce::close(c, conf_path).await?),
+            _ => Err(anyhow!("{INVALID_COMMAND}")),
+        },
+        Some(("capacity", c)) => match c.subcommand() {
+            Some(("watch", w)) => Ok(capacity::watch(w, conf_path).await?),
+            _ => Err(anyhow!("{INVALID_COMMAND}")),
+        },
+        Some(("history", h)) => Ok(ledger::history(h, conf_path).await?),
         Some(("swap-tokens", st)) => Ok(swapper::swap_tokens(st, conf_path).await?),
         _ => Err(anyhow!("{INVALID_COMMAND}")),
     }
@@ -120,6 +371,13 @@ fn keypair_type_flag() -> Arg {
         .required(true)
 }
 
+fn nonce_seed_flag() -> Arg {
+    Arg::new("nonce-seed")
+        .long("nonce-seed")
+        .help("seed each wallet's nonce account address is derived from")
+        .default_value(tx_sender::nonce::DEFAULT_NONCE_SEED)
+}
+
 fn debug_flag() -> Arg {
     Arg::new("debug")
         .long("debug")
@@ -0,0 +1,99 @@
+//! manages the durable nonce accounts auto-deposit signs deposits against ahead of time.
+//! every configured wallet gets its own nonce account, derived from the wallet and a seed
+
+use anyhow::{Context, Result};
+use config::Configuration;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, instruction::Instruction, nonce::State,
+    signature::Keypair, signer::Signer, system_instruction, transaction::Transaction,
+};
+use tx_sender::nonce::{nonce_address, DurableNonce};
+
+/// creates a nonce account for every configured wallet that doesn't have one yet
+pub async fn create(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let rpc = conf.rpc();
+    let seed = matches.get_one::<String>("nonce-seed").unwrap();
+    let lamports = rpc
+        .get_minimum_balance_for_rent_exemption(State::size())
+        .await?;
+    for keypair in conf.load_keypairs()? {
+        let owner = keypair.pubkey();
+        let nonce_account = nonce_address(&owner, seed)?;
+        if rpc
+            .get_account_with_commitment(&nonce_account, CommitmentConfig::confirmed())
+            .await?
+            .value
+            .is_some()
+        {
+            log::info!("nonce account {nonce_account} for {owner} already exists");
+            continue;
+        }
+        let ixs = system_instruction::create_nonce_account_with_seed(
+            &owner,
+            &nonce_account,
+            &owner,
+            seed,
+            &owner,
+            lamports,
+        );
+        let sig = send(&rpc, &keypair, &ixs).await?;
+        log::info!("created nonce account {nonce_account} for {owner} {sig}");
+    }
+    Ok(())
+}
+
+/// advances the nonce of every configured wallet, invalidating deposits signed against it
+pub async fn advance(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let rpc = conf.rpc();
+    let seed = matches.get_one::<String>("nonce-seed").unwrap();
+    for keypair in conf.load_keypairs()? {
+        let nonce = DurableNonce::fetch(
+            &rpc,
+            nonce_address(&keypair.pubkey(), seed)?,
+            CommitmentConfig::confirmed(),
+        )
+        .await?;
+        let sig = send(&rpc, &keypair, &[nonce.advance_ix()]).await?;
+        log::info!("advanced nonce account {} {sig}", nonce.account);
+    }
+    Ok(())
+}
+
+/// withdraws the entire balance of every configured wallet's nonce account, closing it
+pub async fn close(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let rpc = conf.rpc();
+    let seed = matches.get_one::<String>("nonce-seed").unwrap();
+    for keypair in conf.load_keypairs()? {
+        let owner = keypair.pubkey();
+        let nonce_account = nonce_address(&owner, seed)?;
+        let lamports = match rpc
+            .get_account_with_commitment(&nonce_account, CommitmentConfig::confirmed())
+            .await?
+            .value
+        {
+            Some(account) => account.lamports,
+            None => {
+                log::info!("{owner} has no nonce account {nonce_account}");
+                continue;
+            }
+        };
+        let ix = system_instruction::withdraw_nonce_account(&nonce_account, &owner, &owner, lamports);
+        let sig = send(&rpc, &keypair, &[ix]).await?;
+        log::info!("closed nonce account {nonce_account}, returning {lamports} lamports to {owner} {sig}");
+    }
+    Ok(())
+}
+
+async fn send(rpc: &RpcClient, keypair: &Keypair, ixs: &[Instruction]) -> Result<String> {
+    let mut tx = Transaction::new_with_payer(ixs, Some(&keypair.pubkey()));
+    tx.sign(&vec![keypair], rpc.get_latest_blockhash().await?);
+    let sig = rpc
+        .send_and_confirm_transaction(&tx)
+        .await
+        .with_context(|| "failed to send nonce transaction")?;
+    Ok(sig.to_string())
+}
@@ -0,0 +1,237 @@
+//! watches the jlp pool account over a websocket subscription, falling back to rpc polling
+//! whenever the subscription can't be established or drops
+
+use anchor_lang::AnchorDeserialize;
+use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::RpcAccountInfoConfig,
+};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// how long to poll for before attempting to resubscribe
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// a decoded pool account along with the slot it was observed at
+#[derive(Clone)]
+pub struct PoolUpdate {
+    pub slot: u64,
+    pub pool: perpetuals::Pool,
+}
+
+impl PoolUpdate {
+    /// returns true if the pool's aum is below its cap, leaving room for deposits
+    pub fn has_capacity(&self) -> bool {
+        self.pool.aum_usd < self.pool.limit.max_aum_usd
+    }
+}
+
+pub struct PoolWatcher {
+    rpc: Arc<RpcClient>,
+    ws_url: String,
+    pool: Pubkey,
+    poll_interval: Duration,
+}
+
+impl PoolWatcher {
+    pub fn new(rpc: Arc<RpcClient>, ws_url: String, pool: Pubkey, poll_interval: Duration) -> Self {
+        Self {
+            rpc,
+            ws_url,
+            pool,
+            poll_interval,
+        }
+    }
+    /// starts watching the pool in the background, returning a receiver that is updated
+    /// with every observed change to the pool account
+    pub fn spawn(self) -> watch::Receiver<Option<PoolUpdate>> {
+        let (update_tx, update_rx) = watch::channel(None);
+        tokio::task::spawn(async move { self.run(update_tx).await });
+        update_rx
+    }
+    async fn run(self, update_tx: watch::Sender<Option<PoolUpdate>>) {
+        loop {
+            match self.subscribe(&update_tx).await {
+                Ok(()) => log::warn!("pool subscription closed, falling back to polling"),
+                Err(err) => log::warn!("pool subscription failed {err:#?}, falling back to polling"),
+            }
+            if update_tx.is_closed() {
+                return;
+            }
+            self.poll(&update_tx).await;
+            if update_tx.is_closed() {
+                return;
+            }
+        }
+    }
+    /// streams pool updates until the subscription drops
+    async fn subscribe(&self, update_tx: &watch::Sender<Option<PoolUpdate>>) -> Result<()> {
+        let pubsub = PubsubClient::new(&self.ws_url).await?;
+        let (mut stream, unsubscribe) = pubsub
+            .account_subscribe(
+                &self.pool,
+                Some(RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(CommitmentConfig::processed()),
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        log::info!("subscribed to pool account {}", self.pool);
+        // seed the receiver with the current state, as notifications are only sent on change
+        if update_tx.send(Some(self.fetch().await?)).is_err() {
+            return Ok(());
+        }
+        while let Some(response) = stream.next().await {
+            let account = match response.value.decode::<Account>() {
+                Some(account) => account,
+                None => {
+                    log::error!("failed to decode pool account update");
+                    continue;
+                }
+            };
+            match decode_pool(&account.data) {
+                Ok(pool) => {
+                    if update_tx
+                        .send(Some(PoolUpdate {
+                            slot: response.context.slot,
+                            pool,
+                        }))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(err) => log::error!("failed to deserialize pool update {err:#?}"),
+            }
+        }
+        unsubscribe().await;
+        Ok(())
+    }
+    /// polls the pool account for RESUBSCRIBE_INTERVAL
+    async fn poll(&self, update_tx: &watch::Sender<Option<PoolUpdate>>) {
+        let mut ticker = tokio::time::interval(self.poll_interval);
+        let deadline = tokio::time::Instant::now() + RESUBSCRIBE_INTERVAL;
+        while tokio::time::Instant::now() < deadline {
+            ticker.tick().await;
+            match self.fetch().await {
+                Ok(update) => {
+                    if update_tx.send(Some(update)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => log::error!("failed to poll pool account {err:#?}"),
+            }
+        }
+    }
+    async fn fetch(&self) -> Result<PoolUpdate> {
+        let response = self
+            .rpc
+            .get_account_with_commitment(&self.pool, CommitmentConfig::processed())
+            .await?;
+        let account = response
+            .value
+            .with_context(|| "pool account does not exist")?;
+        Ok(PoolUpdate {
+            slot: response.context.slot,
+            pool: decode_pool(&account.data)?,
+        })
+    }
+}
+
+fn decode_pool(data: &[u8]) -> Result<perpetuals::Pool> {
+    if data.len() < 8 {
+        return Err(anyhow!("pool account data too short"));
+    }
+    Ok(perpetuals::Pool::deserialize(&mut &data[8..])?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::AnchorSerialize;
+    fn pool(aum_usd: u128, max_aum_usd: u128) -> perpetuals::Pool {
+        perpetuals::Pool {
+            name: "Pool".to_string(),
+            custodies: vec![Pubkey::new_unique()],
+            aum_usd,
+            limit: perpetuals::Limit {
+                max_aum_usd,
+                max_individual_lp_token: 0,
+                max_position_usd: 0,
+            },
+            fees: perpetuals::Fees {
+                increase_position_bps: 6,
+                decrease_position_bps: 6,
+                add_remove_liquidity_bps: 10,
+                swap_bps: 10,
+                tax_bps: 50,
+                stable_swap_bps: 2,
+                stable_swap_tax_bps: 20,
+                liquidation_reward_bps: 50,
+                protocol_share_bps: 2_500,
+            },
+            pool_apr: perpetuals::PoolApr {
+                last_updated: 0,
+                fee_apr_bps: 0,
+                realized_fee_usd: 0,
+            },
+            max_request_execution_sec: 45,
+            bump: 252,
+            lp_token_bump: 254,
+            inception_time: 0,
+        }
+    }
+    #[test]
+    fn test_decode_pool() {
+        let mut data = vec![0_u8; 8];
+        data.extend(pool(1_000, 2_000).try_to_vec().unwrap());
+        let decoded = decode_pool(&data).unwrap();
+        assert_eq!(decoded.aum_usd, 1_000);
+        assert_eq!(decoded.limit.max_aum_usd, 2_000);
+        assert_eq!(decoded.fees.tax_bps, 50);
+        assert_eq!(decoded.inception_time, 0);
+        assert!(decode_pool(&data[..4]).is_err());
+        assert!(decode_pool(&data[..40]).is_err());
+    }
+    #[test]
+    fn test_has_capacity() {
+        let update = |aum_usd, max_aum_usd| PoolUpdate {
+            slot: 1,
+            pool: pool(aum_usd, max_aum_usd),
+        };
+        assert!(update(1_000, 2_000).has_capacity());
+        assert!(!update(2_000, 2_000).has_capacity());
+        assert!(!update(2_001, 2_000).has_capacity());
+    }
+
+    /// requires a local solana-test-validator with the pool account cloned, ie
+    /// `solana-test-validator --url mainnet-beta --clone 5BUwFW4nRbftYTDMbgxykoFWqWHPzahFSNAaaaJtVKsq`,
+    /// run with `cargo test -p cli -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_poll_fallback() {
+        let rpc = Arc::new(RpcClient::new("http://127.0.0.1:8899".to_string()));
+        let pool = crate::check_jlp_liquidity::POOL_ACCT.parse().unwrap();
+        // nothing listens on the websocket port, so the watcher falls back to polling
+        let mut update_rx = PoolWatcher::new(
+            rpc,
+            "ws://127.0.0.1:1".to_string(),
+            pool,
+            Duration::from_millis(100),
+        )
+        .spawn();
+        tokio::time::timeout(Duration::from_secs(10), update_rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        let update = update_rx.borrow().clone().unwrap();
+        assert!(update.slot > 0);
+        assert!(update.pool.limit.max_aum_usd > 0);
+    }
+}
@@ -0,0 +1,110 @@
+//! deposits of tokens that aren't jlp custodies, swapped into a custody token through jupiter
+//! in the same transaction as the add liquidity instruction so no capacity is lost in between
+
+use anyhow::{Context, Result};
+use jupiter_api::{
+    client::Client,
+    quote_types::RequestOption,
+    swap_types::SwapResponse,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    address_lookup_table_account::AddressLookupTableAccount, instruction::Instruction,
+    pubkey::Pubkey,
+};
+
+/// keeps the jupiter route small enough to share a transaction with add liquidity
+const MAX_SWAP_ACCOUNTS: usize = 30;
+
+/// a quoted swap of the deposit token into a custody token
+pub struct PreSwapQuote {
+    /// the deposit tokens swapped, passed as token_amount_pre_swap
+    pub input_amount: u64,
+    /// the minimum custody tokens the swap delivers, deposited as token_amount_in
+    pub custody_amount: u64,
+    pub swap: SwapResponse,
+}
+
+impl PreSwapQuote {
+    /// returns the jupiter setup and swap instructions, which must run before add liquidity
+    pub fn instructions(&self) -> Result<Vec<Instruction>> {
+        self.swap.instructions(None, None)
+    }
+    pub async fn address_lookup_tables(
+        &self,
+        rpc: &RpcClient,
+    ) -> Result<Vec<AddressLookupTableAccount>> {
+        self.swap.load_address_lookup_tables(rpc).await
+    }
+}
+
+pub struct PreSwapper {
+    api: Client,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    custody_mint: Pubkey,
+    slippage_bps: u64,
+}
+
+impl PreSwapper {
+    pub fn new(
+        owner: Pubkey,
+        input_mint: Pubkey,
+        custody_mint: Pubkey,
+        slippage_bps: u64,
+    ) -> Result<Self> {
+        Ok(Self {
+            api: Client::new()?,
+            owner,
+            input_mint,
+            custody_mint,
+            slippage_bps,
+        })
+    }
+    /// quotes swapping `input_amount` into the custody token, shrinking the input once if the
+    /// swap would deliver more than `max_custody_amount`. returns None if nothing can be swapped
+    pub async fn quote(
+        &self,
+        input_amount: u64,
+        max_custody_amount: u64,
+    ) -> Result<Option<PreSwapQuote>> {
+        let mut input_amount = input_amount;
+        for _ in 0..2 {
+            if input_amount == 0 || max_custody_amount == 0 {
+                return Ok(None);
+            }
+            let quote = self
+                .api
+                .new_quote(
+                    &self.input_mint.to_string(),
+                    &self.custody_mint.to_string(),
+                    input_amount,
+                    &[
+                        RequestOption::SlippageBps(self.slippage_bps),
+                        RequestOption::MaxAccounts(MAX_SWAP_ACCOUNTS),
+                    ],
+                )
+                .await?;
+            let custody_amount = quote
+                .other_amount_threshold
+                .parse::<u64>()
+                .with_context(|| "failed to parse quote threshold")?;
+            if custody_amount > max_custody_amount {
+                input_amount = (input_amount as u128 * max_custody_amount as u128
+                    / custody_amount as u128) as u64;
+                continue;
+            }
+            // cleanup is omitted from the swap instructions, so wrapped sol stays wrapped for the deposit
+            let swap = self
+                .api
+                .new_swap(quote, &self.owner.to_string(), true)
+                .await?;
+            return Ok(Some(PreSwapQuote {
+                input_amount,
+                custody_amount,
+                swap,
+            }));
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,189 @@
+//! ranks the pool's custodies by the fee predicted for depositing a usd amount through each,
+//! as depositing into an overweight custody is taxed on top of the base fee
+
+use crate::check_jlp_liquidity::{PERPETUALS_ACCT, POOL_ACCT};
+use anchor_lang::AnchorDeserialize;
+use anyhow::{Context, Result};
+use config::Configuration;
+use perpetuals::fees::{liquidity_fees, LiquidityFee};
+use perpetuals::jlp_cacher::{
+    token_to_usd_amount, usd_to_token_amount, usd_to_ui, JLPCacheAccountKeys, USD_DECIMALS,
+};
+use perpetuals::oracle::CustodyPrice;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use std::str::FromStr;
+
+/// usd amounts are in dollars
+#[derive(Serialize, Debug, Clone)]
+pub struct CustodyQuote {
+    pub mint: String,
+    #[serde(skip)]
+    pub mint_key: Pubkey,
+    /// tokens worth the quoted usd amount, in the custody token's smallest unit
+    #[serde(skip)]
+    pub token_amount: u64,
+    pub weight_pct: f64,
+    pub target_weight_pct: f64,
+    pub next_weight_pct: f64,
+    pub deposit_fee_bps: u64,
+    pub deposit_fee_usd: f64,
+    pub withdraw_fee_bps: u64,
+}
+
+/// predicts the fee for depositing and withdrawing `amount_usd` (scaled by USD_DECIMALS)
+/// through every custody, cheapest deposit first
+pub async fn rank_custodies(
+    rpc: &RpcClient,
+    jlp_cache_accounts: &JLPCacheAccountKeys,
+    amount_usd: u128,
+) -> Result<Vec<CustodyQuote>> {
+    let (fees, custodies, prices) = load_pricing(rpc, jlp_cache_accounts).await?;
+    quotes(&fees, &custodies, &prices, amount_usd)
+}
+
+/// warns when depositing `token_amount` of `custody_mint` is predicted to cost more than
+/// depositing the same value through the cheapest custody
+pub async fn warn_if_costlier(
+    rpc: &RpcClient,
+    jlp_cache_accounts: &JLPCacheAccountKeys,
+    custody_mint: Pubkey,
+    token_amount: u64,
+) -> Result<()> {
+    let (fees, custodies, prices) = load_pricing(rpc, jlp_cache_accounts).await?;
+    let (custody, price) = custodies
+        .iter()
+        .zip(&prices)
+        .find(|(custody, _)| custody.mint == custody_mint)
+        .with_context(|| format!("{custody_mint} is not a jlp custody token"))?;
+    let amount_usd = token_to_usd_amount(token_amount, price.price.usd()?, custody.decimals);
+    let quotes = quotes(&fees, &custodies, &prices, amount_usd)?;
+    let chosen = quotes
+        .iter()
+        .find(|quote| quote.mint_key == custody_mint)
+        .with_context(|| format!("no quote for {custody_mint}"))?;
+    match quotes.first() {
+        Some(cheapest) if cheapest.deposit_fee_bps < chosen.deposit_fee_bps => log::warn!(
+            "depositing into {custody_mint} is predicted to cost {} bps (${:.2}), {} would cost {} bps (${:.2})",
+            chosen.deposit_fee_bps,
+            chosen.deposit_fee_usd,
+            cheapest.mint,
+            cheapest.deposit_fee_bps,
+            cheapest.deposit_fee_usd
+        ),
+        _ => log::info!(
+            "depositing into {custody_mint} is predicted to cost {} bps (${:.2})",
+            chosen.deposit_fee_bps,
+            chosen.deposit_fee_usd
+        ),
+    }
+    Ok(())
+}
+
+async fn load_pricing(
+    rpc: &RpcClient,
+    jlp_cache_accounts: &JLPCacheAccountKeys,
+) -> Result<(perpetuals::Fees, Vec<perpetuals::Custody>, Vec<CustodyPrice>)> {
+    let pool = perpetuals::Pool::deserialize(
+        &mut &rpc.get_account_data(&jlp_cache_accounts.pool).await?[8..],
+    )?;
+    let custodies = jlp_cache_accounts.load_custodies(rpc).await?;
+    let prices = jlp_cache_accounts.oracle_prices(rpc, &custodies).await?;
+    Ok((pool.fees, custodies, prices))
+}
+
+fn quotes(
+    fees: &perpetuals::Fees,
+    custodies: &[perpetuals::Custody],
+    prices: &[CustodyPrice],
+    amount_usd: u128,
+) -> Result<Vec<CustodyQuote>> {
+    let deposits = liquidity_fees(fees, custodies, prices, amount_usd, true)?;
+    let withdrawals = liquidity_fees(fees, custodies, prices, amount_usd, false)?;
+    let bps_pct = |bps: u64| bps as f64 / 100.0;
+    let mut quotes = Vec::with_capacity(custodies.len());
+    for (((custody, price), deposit), withdrawal) in
+        custodies.iter().zip(prices).zip(&deposits).zip(&withdrawals)
+    {
+        let LiquidityFee {
+            fee_bps,
+            fee_usd,
+            current_weight_bps,
+            next_weight_bps,
+        } = *deposit;
+        quotes.push(CustodyQuote {
+            mint: custody.mint.to_string(),
+            mint_key: custody.mint,
+            token_amount: usd_to_token_amount(amount_usd, price.price.usd()?, custody.decimals),
+            weight_pct: bps_pct(current_weight_bps),
+            target_weight_pct: bps_pct(custody.target_ratio_bps),
+            next_weight_pct: bps_pct(next_weight_bps),
+            deposit_fee_bps: fee_bps,
+            deposit_fee_usd: usd_to_ui(fee_usd),
+            withdraw_fee_bps: withdrawal.fee_bps,
+        });
+    }
+    quotes.sort_by_key(|quote| quote.deposit_fee_bps);
+    Ok(quotes)
+}
+
+pub async fn quote_deposit(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let rpc = conf.rpc();
+    let amount_usd = spl_token::ui_amount_to_amount(
+        *matches.get_one::<f64>("amount-usd").unwrap(),
+        USD_DECIMALS,
+    ) as u128;
+
+    let pool = Pubkey::from_str(POOL_ACCT).unwrap();
+    let perp = Pubkey::from_str(PERPETUALS_ACCT).unwrap();
+    let jlp_cache_accounts = JLPCacheAccountKeys::load_account_keys(&rpc, perp, pool).await?;
+    let quotes = rank_custodies(&rpc, &jlp_cache_accounts, amount_usd).await?;
+
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&quotes)?);
+    } else {
+        println!(
+            "{:<44} {:>8} {:>8} {:>8} {:>8} {:>12} {:>9}",
+            "mint", "weight%", "target%", "next%", "fee_bps", "fee_usd", "withdraw"
+        );
+        for quote in &quotes {
+            println!(
+                "{:<44} {:>8.2} {:>8.2} {:>8.2} {:>8} {:>12.2} {:>9}",
+                quote.mint,
+                quote.weight_pct,
+                quote.target_weight_pct,
+                quote.next_weight_pct,
+                quote.deposit_fee_bps,
+                quote.deposit_fee_usd,
+                quote.withdraw_fee_bps,
+            );
+        }
+    }
+
+    if matches.get_flag("verify") {
+        // only pays for the simulated views
+        let payer = conf.keypair.keypair()?.pubkey();
+        for quote in &quotes {
+            match jlp_cache_accounts
+                .quote_add_liquidity(&rpc, payer, quote.mint_key, quote.token_amount)
+                .await
+            {
+                Ok(quoted) if quoted.fee_bps == quote.deposit_fee_bps => log::info!(
+                    "{} deposit fee predicted as {} bps, matching the program",
+                    quote.mint,
+                    quote.deposit_fee_bps
+                ),
+                Ok(quoted) => log::warn!(
+                    "{} deposit fee predicted as {} bps but the program charges {} bps",
+                    quote.mint,
+                    quote.deposit_fee_bps,
+                    quoted.fee_bps
+                ),
+                Err(err) => log::error!("failed to quote a deposit of {} {err:#?}", quote.mint),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Context, Result};
+use config::Configuration;
+use perpetuals::jlp_cacher::{apply_slippage, unwrap_sol_ix, JLPCacheAccountKeys, LP_TOKEN_MINT};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, program_pack::Pack, signer::Signer,
+    transaction::Transaction,
+};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use std::str::FromStr;
+
+use crate::check_jlp_liquidity::{PERPETUALS_ACCT, POOL_ACCT};
+
+/// redeems jlp for one of the pool's custody tokens
+pub async fn remove_liquidity(matches: &clap::ArgMatches, conf_path: &str) -> Result<()> {
+    let conf = Configuration::load(conf_path)?;
+    let rpc = conf.rpc();
+    let keypair_contents = conf.keypair.contents();
+    // expect it to be pk
+    let keypair = Keypair::from_base58_string(&keypair_contents);
+    let owner = keypair.pubkey();
+
+    let receive_mint = matches.get_one::<String>("receive-mint").unwrap();
+    let receive_mint = Pubkey::from_str(receive_mint)?;
+    let slippage_bps = *matches.get_one::<u64>("slippage-bps").unwrap();
+    let priority_fee = matches.get_one::<f64>("priority-fee").unwrap();
+    let priority_fee = spl_token::ui_amount_to_amount(*priority_fee, 9);
+
+    let pool_acct = Pubkey::from_str(POOL_ACCT).unwrap();
+    let perp_acct = Pubkey::from_str(PERPETUALS_ACCT).unwrap();
+    let jlp_account_cache =
+        JLPCacheAccountKeys::load_account_keys(&rpc, perp_acct, pool_acct).await?;
+    if jlp_account_cache.custody_account_for_mint(receive_mint).is_none() {
+        return Err(anyhow!("{receive_mint} is not a jlp custody token"));
+    }
+
+    let acct_data = rpc.get_account_data(&receive_mint).await?;
+    let receive_mint_acct = spl_token::state::Mint::unpack(&acct_data[..])?;
+    let acct_data = rpc.get_account_data(&LP_TOKEN_MINT).await?;
+    let lp_mint_acct = spl_token::state::Mint::unpack(&acct_data[..])?;
+
+    let lp_ata = spl_associated_token_account::get_associated_token_address(&owner, &LP_TOKEN_MINT);
+    let acct_data = rpc
+        .get_account_data(&lp_ata)
+        .await
+        .with_context(|| "failed to fetch jlp token account")?;
+    let lp_balance = spl_token::state::Account::unpack(&acct_data[..])?.amount;
+
+    // redeem the entire balance unless an amount is given
+    let lp_amount_in = match matches.get_one::<f64>("lp-amount") {
+        Some(lp_amount) => spl_token::ui_amount_to_amount(*lp_amount, lp_mint_acct.decimals),
+        None => lp_balance,
+    };
+    if lp_amount_in == 0 || lp_amount_in > lp_balance {
+        return Err(anyhow!(
+            "invalid lp amount {}, balance is {}",
+            spl_token::amount_to_ui_amount(lp_amount_in, lp_mint_acct.decimals),
+            spl_token::amount_to_ui_amount(lp_balance, lp_mint_acct.decimals)
+        ));
+    }
+
+    // quote the exact amount received from the program, accounting for the remove liquidity fee and tax
+    let quote = jlp_account_cache
+        .quote_remove_liquidity(&rpc, owner, receive_mint, lp_amount_in)
+        .await?;
+    let min_out = apply_slippage(quote.amount, slippage_bps);
+
+    log::info!(
+        "redeeming {} jlp for expected {} {} (fee {} bps), min out {}",
+        spl_token::amount_to_ui_amount(lp_amount_in, lp_mint_acct.decimals),
+        spl_token::amount_to_ui_amount(quote.amount, receive_mint_acct.decimals),
+        receive_mint,
+        quote.fee_bps,
+        spl_token::amount_to_ui_amount(min_out, receive_mint_acct.decimals),
+    );
+
+    let mut ixs = vec![
+        ComputeBudgetInstruction::set_compute_unit_price(priority_fee),
+        ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &owner,
+            &owner,
+            &receive_mint,
+            &spl_token::id(),
+        ),
+        jlp_account_cache.generate_liquidity_remove_ix(receive_mint, owner, lp_amount_in, min_out)?,
+    ];
+    if receive_mint.eq(&spl_token::native_mint::id()) {
+        ixs.push(unwrap_sol_ix(owner));
+    }
+
+    let mut tx = Transaction::new_with_payer(&ixs, Some(&owner));
+    tx.sign(&vec![&keypair], rpc.get_latest_blockhash().await?);
+    let sig = rpc.send_and_confirm_transaction(&tx).await?;
+    log::info!("sent remove liquidity {}", sig);
+    Ok(())
+}
@@ -0,0 +1,368 @@
+//! swaps jlp minted by auto-deposit back into usdc. each swap runs through an explicit state
+//! machine, fetching the balance, quoting, building, sending, confirming and finally verifying
+//! the balance changes, with a fresh quote for every bounded retry
+
+use anyhow::{anyhow, Result};
+use config::swap_back::SwapBackPolicy;
+use jupiter_api::{
+    client::Client, quote_types::QuoteResponse, swap_types::SwapResponse, swapper::Swapper,
+};
+use perpetuals::jlp_cacher::{JLPCacheAccountKeys, LP_TOKEN_MINT};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey,
+    signature::Signature, signer::Signer,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tx_sender::{
+    confirmation::{token_balance_change, ConfirmationTracker, TxOutcome},
+    sender::Sent,
+};
+
+use crate::auto_depositor::DepositLanded;
+use crate::ledger::{Ledger, LedgerEvent};
+
+pub const USDC_MINT: Pubkey = solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+/// how the swap back of a single request ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwapBackOutcome {
+    /// the swap landed and its balance changes were verified
+    Swapped,
+    /// nothing was swapped, either by policy or because there was nothing to swap
+    Skipped(String),
+    /// the swap could not be completed within the retry limit, or landed with unexpected balance changes
+    Failed(String),
+}
+
+/// the structured result of a swap back request
+#[derive(Debug, Clone)]
+pub struct SwapBackReport {
+    pub outcome: SwapBackOutcome,
+    /// jlp the final attempt tried to swap
+    pub amount_in: u64,
+    /// usdc quoted by the final attempt
+    pub expected_out: u64,
+    /// the last signature sent, if any
+    pub signature: Option<Signature>,
+    /// verified jlp and usdc balance changes of a landed swap
+    pub jlp_delta: Option<i128>,
+    pub usdc_delta: Option<i128>,
+    pub attempts: usize,
+}
+
+impl std::fmt::Display for SwapBackReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} amount_in={} expected_out={} signature={} jlp_delta={:?} usdc_delta={:?} attempts={}",
+            self.outcome,
+            self.amount_in,
+            self.expected_out,
+            self.signature
+                .map(|sig| sig.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            self.jlp_delta,
+            self.usdc_delta,
+            self.attempts,
+        )
+    }
+}
+
+/// a quoted swap that is carried through the later states
+struct Quoted {
+    amount: u64,
+    expected_out: u64,
+    min_out: u64,
+}
+
+enum State {
+    FetchBalance,
+    Quote { amount: u64 },
+    Build { quoted: Quoted, quote: QuoteResponse },
+    Send { quoted: Quoted, swap: SwapResponse },
+    Confirm { quoted: Quoted, sent: Sent },
+    Verify { quoted: Quoted, signature: Signature },
+    Done(SwapBackOutcome),
+}
+
+pub struct SwapBack {
+    swapper: Arc<Swapper>,
+    api: Client,
+    tracker: ConfirmationTracker,
+    jlp_account_cache: JLPCacheAccountKeys,
+    policy: SwapBackPolicy,
+    max_attempts: usize,
+    retry_delay: Duration,
+    /// jlp minted by this run's deposits that hasn't been swapped back yet
+    unswapped_minted: u64,
+}
+
+impl SwapBack {
+    pub fn new(
+        swapper: Arc<Swapper>,
+        jlp_account_cache: JLPCacheAccountKeys,
+        policy: SwapBackPolicy,
+        commitment: CommitmentConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            tracker: ConfirmationTracker::new(swapper.rpc.clone(), commitment),
+            swapper,
+            api: Client::new()?,
+            jlp_account_cache,
+            policy,
+            max_attempts: 3,
+            retry_delay: Duration::from_secs(1),
+            unswapped_minted: 0,
+        })
+    }
+    pub fn record_minted(&mut self, lp_amount: u64) {
+        self.unswapped_minted = self.unswapped_minted.saturating_add(lp_amount);
+    }
+    /// runs a single swap back request through to a final outcome
+    pub async fn run(&mut self) -> SwapBackReport {
+        let mut report = SwapBackReport {
+            outcome: SwapBackOutcome::Skipped(String::new()),
+            amount_in: 0,
+            expected_out: 0,
+            signature: None,
+            jlp_delta: None,
+            usdc_delta: None,
+            attempts: 1,
+        };
+        let mut state = State::FetchBalance;
+        loop {
+            state = match state {
+                State::FetchBalance => match self.fetch_balance().await {
+                    Ok(balance) => {
+                        let amount = self.policy.swap_amount(balance, self.unswapped_minted);
+                        if amount == 0 {
+                            State::Done(SwapBackOutcome::Skipped(format!(
+                                "nothing to swap back under policy {}",
+                                self.policy
+                            )))
+                        } else {
+                            State::Quote { amount }
+                        }
+                    }
+                    Err(err) => self.retry(&mut report, "fetch balance", err).await,
+                },
+                State::Quote { amount } => match self.quote(amount).await {
+                    Ok(Some((quoted, quote))) => {
+                        report.amount_in = quoted.amount;
+                        report.expected_out = quoted.expected_out;
+                        State::Build { quoted, quote }
+                    }
+                    Ok(None) => State::Done(SwapBackOutcome::Skipped(format!(
+                        "market quote is below the {} premium",
+                        self.policy
+                    ))),
+                    Err(err) => self.retry(&mut report, "quote", err).await,
+                },
+                State::Build { quoted, quote } => match self
+                    .api
+                    .new_swap(quote, &self.swapper.keypair().pubkey().to_string(), true)
+                    .await
+                {
+                    Ok(swap) => State::Send { quoted, swap },
+                    Err(err) => self.retry(&mut report, "build", err).await,
+                },
+                State::Send { quoted, swap } => match self
+                    .swapper
+                    .send_swap(swap, self.tracker.commitment(), false, 0)
+                    .await
+                {
+                    Ok(sent) => {
+                        log::info!("sent swap back {}", sent.signature);
+                        report.signature = Some(sent.signature);
+                        State::Confirm { quoted, sent }
+                    }
+                    Err(err) => self.retry(&mut report, "send", err).await,
+                },
+                State::Confirm { quoted, sent } => match sent.outcome {
+                    TxOutcome::Landed { .. } => State::Verify {
+                        quoted,
+                        signature: sent.signature,
+                    },
+                    TxOutcome::Failed { slot, err } => {
+                        let err = anyhow!("swap {} failed in slot {slot} {err}", sent.signature);
+                        self.retry(&mut report, "confirm", err).await
+                    }
+                    TxOutcome::Rejected { err } => {
+                        let err = anyhow!("swap rejected by preflight {err}");
+                        self.retry(&mut report, "confirm", err).await
+                    }
+                    TxOutcome::Expired => {
+                        let err = anyhow!("swap {} expired", sent.signature);
+                        self.retry(&mut report, "confirm", err).await
+                    }
+                    // it may still land, so it can't be retried
+                    TxOutcome::Pending => State::Done(SwapBackOutcome::Failed(format!(
+                        "swap {} stopped being tracked before landing",
+                        sent.signature
+                    ))),
+                },
+                // a landed swap is never retried, as that could swap twice
+                State::Verify { quoted, signature } => {
+                    match self.verify(&mut report, &quoted, &signature).await {
+                        Ok(()) => {
+                            self.unswapped_minted =
+                                self.unswapped_minted.saturating_sub(quoted.amount);
+                            State::Done(SwapBackOutcome::Swapped)
+                        }
+                        Err(err) => State::Done(SwapBackOutcome::Failed(format!(
+                            "swap {signature} landed but failed verification {err:#}"
+                        ))),
+                    }
+                }
+                State::Done(outcome) => {
+                    report.outcome = outcome;
+                    return report;
+                }
+            };
+        }
+    }
+    /// returns the state to continue from after a retryable error, starting over with a
+    /// fresh balance and quote until the attempts are used up
+    async fn retry(
+        &self,
+        report: &mut SwapBackReport,
+        step: &str,
+        err: anyhow::Error,
+    ) -> State {
+        log::error!("swap back {step} failed on attempt {} {err:#?}", report.attempts);
+        if report.attempts >= self.max_attempts {
+            return State::Done(SwapBackOutcome::Failed(format!(
+                "{step} failed after {} attempts {err:#}",
+                report.attempts
+            )));
+        }
+        report.attempts += 1;
+        tokio::time::sleep(self.retry_delay).await;
+        State::FetchBalance
+    }
+    async fn fetch_balance(&self) -> Result<u64> {
+        let jlp_ata = spl_associated_token_account::get_associated_token_address(
+            &self.swapper.keypair().pubkey(),
+            &LP_TOKEN_MINT,
+        );
+        // read the balance at the same commitment deposits are confirmed at
+        let response = self
+            .swapper
+            .rpc
+            .get_account_with_commitment(&jlp_ata, self.tracker.commitment())
+            .await?;
+        Ok(match response.value {
+            Some(account) => spl_token::state::Account::unpack(&account.data)?.amount,
+            None => 0,
+        })
+    }
+    /// quotes swapping `amount` jlp into usdc, returning None if the policy rejects the quote
+    async fn quote(&self, amount: u64) -> Result<Option<(Quoted, QuoteResponse)>> {
+        let quote = self
+            .api
+            .new_quote(&LP_TOKEN_MINT.to_string(), &USDC_MINT.to_string(), amount, &[])
+            .await?;
+        let expected_out = quote.out_amount.parse::<u64>()?;
+        let min_out = quote.other_amount_threshold.parse::<u64>()?;
+        if let SwapBackPolicy::Premium { .. } = self.policy {
+            // value the jlp at the pool's aum, treating usdc as one usd
+            let nav = self
+                .jlp_account_cache
+                .load_pool_nav(&self.swapper.rpc)
+                .await?
+                .lp_to_usd_amount(amount);
+            if !self.policy.accepts_quote(expected_out, nav) {
+                log::info!("market quote {expected_out} usdc for {amount} jlp is below the premium over nav {nav}");
+                return Ok(None);
+            }
+        }
+        Ok(Some((
+            Quoted {
+                amount,
+                expected_out,
+                min_out,
+            },
+            quote,
+        )))
+    }
+    /// checks that a landed swap removed exactly the quoted jlp and delivered at least the minimum usdc
+    async fn verify(
+        &self,
+        report: &mut SwapBackReport,
+        quoted: &Quoted,
+        signature: &Signature,
+    ) -> Result<()> {
+        let owner = self.swapper.keypair().pubkey();
+        let tx = self.tracker.landed_transaction(signature).await?;
+        let jlp_delta = token_balance_change(&tx, &owner, &LP_TOKEN_MINT)?;
+        let usdc_delta = token_balance_change(&tx, &owner, &USDC_MINT)?;
+        report.jlp_delta = Some(jlp_delta);
+        report.usdc_delta = Some(usdc_delta);
+        if jlp_delta != -(quoted.amount as i128) {
+            return Err(anyhow!("expected jlp change -{}, got {jlp_delta}", quoted.amount));
+        }
+        if usdc_delta < quoted.min_out as i128 {
+            return Err(anyhow!("expected at least {} usdc, got {usdc_delta}", quoted.min_out));
+        }
+        Ok(())
+    }
+}
+
+/// runs a swap back for every landed deposit, reporting each result to the ledger and the deposit loop
+pub async fn swapi_boi(
+    mut swap_back: SwapBack,
+    ledger: Arc<Ledger>,
+    mut swap_trigger: tokio::sync::mpsc::Receiver<DepositLanded>,
+    report_tx: tokio::sync::mpsc::Sender<SwapBackReport>,
+    mut exit_rx: tokio::sync::oneshot::Receiver<()>,
+) -> Result<()> {
+    let owner_str = swap_back.swapper.keypair().pubkey().to_string();
+    loop {
+        log::info!("waiting for swap requests");
+        tokio::select! {
+            biased;
+            landed = swap_trigger.recv() => match landed {
+                Some(landed) => {
+                    log::info!(
+                        "deposit {} landed with {} jlp minted",
+                        landed.signature,
+                        landed.lp_amount
+                    );
+                    swap_back.record_minted(landed.lp_amount);
+                }
+                None => return Ok(()),
+            },
+            _ = &mut exit_rx => {
+                log::info!("swapi_boi goodbye");
+                return Ok(());
+            }
+        }
+        if swap_back.policy == SwapBackPolicy::Hold {
+            log::info!("holding jlp, {} minted this run", swap_back.unswapped_minted);
+            continue;
+        }
+        let report = swap_back.run().await;
+        if let SwapBackOutcome::Skipped(_) = report.outcome {
+            log::info!("swap back skipped {report}");
+        } else {
+            ledger.record(
+                &owner_str,
+                LedgerEvent::SwapBack {
+                    input_mint: LP_TOKEN_MINT.to_string(),
+                    output_mint: USDC_MINT.to_string(),
+                    amount_in: report.amount_in,
+                    expected_out: report.expected_out,
+                    amount_out: report.usdc_delta.map(|delta| delta.max(0) as u64),
+                    signature: report.signature.map(|sig| sig.to_string()),
+                    error: match &report.outcome {
+                        SwapBackOutcome::Failed(err) => Some(err.clone()),
+                        _ => None,
+                    },
+                },
+            );
+        }
+        // the deposit loop may have stopped already
+        let _ = report_tx.send(report).await;
+    }
+}
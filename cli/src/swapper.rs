@@ -8,6 +8,7 @@ use solana_sdk::{
 use solana_sdk::{pubkey::Pubkey, signature::Keypair};
 use std::str::FromStr;
 use std::sync::Arc;
+use tx_sender::{compute_units::ComputeUnitEstimator, priority_fee::PriorityFeeEstimator};
 use tokio::signal::unix::{Signal, SignalKind};
 
 use crate::check_jlp_liquidity::{PERPETUALS_ACCT, POOL_ACCT};
@@ -28,10 +29,23 @@ pub async fn swap_tokens(matches: &clap::ArgMatches, conf_path: &str) -> Result<
     let swap_amount = spl_token::ui_amount_to_amount(*swap_amount, input_mint_acct.decimals);
 
     let api_client = jupiter_api::client::Client::new()?;
-    let swap_client =  Arc::new(jupiter_api::swapper::Swapper::new(
-        Arc::new(rpc),
-        keypair
-    ));
+    let rpc = Arc::new(rpc);
+    let mut compute_units = ComputeUnitEstimator::new(rpc.clone());
+    if let Some(margin_bps) = conf.compute_unit_margin_bps {
+        compute_units = compute_units.with_margin_bps(margin_bps);
+    }
+    let swap_client = Arc::new(
+        jupiter_api::swapper::Swapper::new(rpc.clone(), keypair)
+            .with_priority_fee(
+                PriorityFeeEstimator::new(rpc.clone())
+                    .with_percentile(conf.priority_fee.percentile)
+                    .with_floor(conf.priority_fee.min_micro_lamports)
+                    .with_ceiling(conf.priority_fee.max_micro_lamports)
+                    .with_escalation_bps(conf.priority_fee.escalation_bps),
+            )
+            .with_compute_units(compute_units)
+            .with_submitter(conf.submitter(rpc.clone()).await?),
+    );
 
 
     let quote = api_client.new_quote(input_mint, output_mint, swap_amount, &[]).await?;
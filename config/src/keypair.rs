@@ -1,4 +1,6 @@
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use solana_sdk::signature::{read_keypair_file, Keypair};
 /// The KeypairType enum is used to allow handling multiple different
 /// types of keypairs being stored in a configuration file in a dependency
 /// free manner
@@ -31,6 +33,19 @@ impl KeypairType {
             Self::Private { value } => value.clone(),
         }
     }
+    /// loads the keypair, expecting private keys to be base58 encoded
+    pub fn keypair(&self) -> Result<Keypair> {
+        match self {
+            Self::FileBased { value } => read_keypair_file(value)
+                .map_err(|err| anyhow!("failed to read keypair file {value} {err:#?}")),
+            Self::Private { value } => Ok(Keypair::from_bytes(
+                &solana_sdk::bs58::decode(value)
+                    .into_vec()
+                    .with_context(|| "failed to decode private key")?,
+            )?),
+            Self::Hardware { .. } => Err(anyhow!("hardware wallets are not supported")),
+        }
+    }
 }
 
 impl Default for KeypairType {
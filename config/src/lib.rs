@@ -3,17 +3,50 @@
 
 use keypair::KeypairType;
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::{str::FromStr, sync::Arc};
+use submitter::SubmitterConfig;
+use tx_sender::submitter::{
+    BundleSubmitter, FanOutSubmitter, RpcSubmitter, Submitter, TpuSubmitter,
+};
 use {
     anyhow::{Context, Result},
     serde::{Deserialize, Serialize},
 };
 
 pub mod keypair;
+pub mod priority_fee;
+pub mod submitter;
+pub mod swap_back;
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Configuration {
     pub keypair: keypair::KeypairType,
+    /// additional wallets auto-deposit runs a deposit pipeline for alongside `keypair`
+    #[serde(default)]
+    pub keypairs: Vec<keypair::KeypairType>,
     pub rpc: String,
+    /// websocket endpoint used for account subscriptions, derived from the rpc url when empty
+    #[serde(default)]
+    pub ws: String,
+    /// path of the jsonl ledger recording deposits and swaps, defaults to ledger.jsonl
+    #[serde(default)]
+    pub ledger: String,
+    /// how much jlp auto-deposit swaps back after each deposit, swapping the entire balance by default
+    #[serde(default)]
+    pub swap_back: swap_back::SwapBackPolicy,
+    /// percentile, bounds and escalation of the priority fee paid by deposits and swaps
+    #[serde(default)]
+    pub priority_fee: priority_fee::PriorityFeeConfig,
+    /// margin in bps added to the compute units deposits and swaps consume in simulation
+    #[serde(default)]
+    pub compute_unit_margin_bps: Option<u64>,
+    /// backend deposits and swaps are submitted through, the rpc by default
+    #[serde(default)]
+    pub submitter: submitter::SubmitterConfig,
 }
 
 impl Configuration {
@@ -57,6 +90,77 @@ impl Configuration {
     pub fn rpc(&self) -> RpcClient {
         RpcClient::new(self.rpc.clone())
     }
+    /// loads `keypair` followed by every additional keypair, skipping duplicates
+    pub fn load_keypairs(&self) -> Result<Vec<Keypair>> {
+        let mut keypairs: Vec<Keypair> = vec![];
+        for keypair_type in std::iter::once(&self.keypair).chain(self.keypairs.iter()) {
+            let keypair = keypair_type.keypair()?;
+            if keypairs.iter().all(|kp| kp.pubkey() != keypair.pubkey()) {
+                keypairs.push(keypair);
+            }
+        }
+        Ok(keypairs)
+    }
+    /// returns the configured submission backend, submitting through `rpc` unless bundling
+    /// or sending to the tpu. tpu backends fetch the leader schedule when they are created
+    pub async fn submitter(&self, rpc: Arc<RpcClient>) -> Result<Arc<dyn Submitter>> {
+        Ok(match &self.submitter {
+            SubmitterConfig::Rpc => Arc::new(RpcSubmitter::new(rpc)),
+            SubmitterConfig::FanOut { urls } => Arc::new(FanOutSubmitter::new(
+                std::iter::once(rpc)
+                    .chain(urls.iter().map(|url| Arc::new(RpcClient::new(url.clone()))))
+                    .collect(),
+            )),
+            SubmitterConfig::Bundle {
+                url,
+                tip_account,
+                tip_lamports,
+            } => Arc::new(BundleSubmitter::new(
+                url.clone(),
+                Pubkey::from_str(tip_account).with_context(|| "invalid bundle tip account")?,
+                *tip_lamports,
+            )?),
+            SubmitterConfig::Tpu {
+                fanout_slots,
+                use_quic,
+                with_rpc,
+            } => {
+                let tpu = Arc::new(
+                    TpuSubmitter::new(rpc.clone(), &self.ws_url(), *fanout_slots, *use_quic)
+                        .await
+                        .with_context(|| "failed to start tpu client")?,
+                );
+                if *with_rpc {
+                    Arc::new(FanOutSubmitter::from_submitters(vec![
+                        tpu,
+                        Arc::new(RpcSubmitter::new(rpc)),
+                    ]))
+                } else {
+                    tpu
+                }
+            }
+        })
+    }
+    pub fn ledger_path(&self) -> String {
+        if self.ledger.is_empty() {
+            "ledger.jsonl".to_string()
+        } else {
+            self.ledger.clone()
+        }
+    }
+    /// returns the websocket url, defaulting to the rpc url with its scheme swapped for ws(s)
+    pub fn ws_url(&self) -> String {
+        if !self.ws.is_empty() {
+            return self.ws.clone();
+        }
+        if let Some(host) = self.rpc.strip_prefix("https://") {
+            format!("wss://{host}")
+        } else if let Some(host) = self.rpc.strip_prefix("http://") {
+            format!("ws://{host}")
+        } else {
+            self.rpc.clone()
+        }
+    }
 }
 
 const SAVE_FAILURE: &str = "failed to save file";
@@ -96,4 +200,65 @@ mod test {
         std::fs::remove_file("conf_fh.yaml").unwrap();
         std::fs::remove_file("conf_hw.yaml").unwrap();
     }
+    #[tokio::test]
+    async fn test_submitter() {
+        let rpc = Arc::new(RpcClient::new("http://127.0.0.1:8899".to_string()));
+        let mut conf: Configuration = serde_yaml::from_str(
+            "keypair: !Private\n  value: replaceme\nrpc: http://127.0.0.1:8899\nsubmitter:\n  kind: bundle\n  url: http://127.0.0.1:1234/api/v1/bundles\n  tip_account: 96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5\n  tip_lamports: 10000\n",
+        )
+        .unwrap();
+        let payer = Pubkey::new_unique();
+        assert_eq!(conf.submitter(rpc.clone()).await.unwrap().tip_instructions(&payer).len(), 1);
+        conf.submitter = SubmitterConfig::Bundle {
+            url: "http://127.0.0.1:1234".to_string(),
+            tip_account: "not a pubkey".to_string(),
+            tip_lamports: 1,
+        };
+        assert!(conf.submitter(rpc.clone()).await.is_err());
+        let conf = Configuration::default();
+        assert_eq!(conf.submitter, SubmitterConfig::Rpc);
+        assert!(conf.submitter(rpc).await.unwrap().tip_instructions(&payer).is_empty());
+        let conf: Configuration = serde_yaml::from_str(
+            "keypair: !Private\n  value: replaceme\nrpc: http://127.0.0.1:8899\nsubmitter:\n  kind: tpu\n  with_rpc: true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            conf.submitter,
+            SubmitterConfig::Tpu {
+                fanout_slots: 12,
+                use_quic: true,
+                with_rpc: true,
+            }
+        );
+    }
+    #[test]
+    fn test_ws_url() {
+        let mut conf = Configuration {
+            rpc: "https://api.mainnet-beta.solana.com".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(conf.ws_url(), "wss://api.mainnet-beta.solana.com");
+        conf.rpc = "http://127.0.0.1:8899".to_string();
+        assert_eq!(conf.ws_url(), "ws://127.0.0.1:8899");
+        conf.ws = "ws://127.0.0.1:8900".to_string();
+        assert_eq!(conf.ws_url(), "ws://127.0.0.1:8900");
+    }
+    #[test]
+    fn test_load_keypairs() {
+        let first = Keypair::new();
+        let second = Keypair::new();
+        let private = |kp: &Keypair| KeypairType::Private {
+            value: kp.to_base58_string(),
+        };
+        let conf = Configuration {
+            keypair: private(&first),
+            keypairs: vec![private(&second), private(&first)],
+            ..Default::default()
+        };
+        let keypairs = conf.load_keypairs().unwrap();
+        assert_eq!(keypairs.len(), 2);
+        assert_eq!(keypairs[0].pubkey(), first.pubkey());
+        assert_eq!(keypairs[1].pubkey(), second.pubkey());
+        assert!(Configuration::default().load_keypairs().is_err());
+    }
 }
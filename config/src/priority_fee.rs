@@ -0,0 +1,27 @@
+//! bounds for the priority fee estimated from recent prioritization fees
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct PriorityFeeConfig {
+    /// percentile (0-100) of the fees recently paid for the transaction's writable accounts
+    pub percentile: u8,
+    /// lowest compute unit price paid, in micro-lamports
+    pub min_micro_lamports: u64,
+    /// highest compute unit price paid, in micro-lamports
+    pub max_micro_lamports: u64,
+    /// increase in bps applied to the fee each time an expired transaction is re-signed
+    pub escalation_bps: u64,
+}
+
+impl Default for PriorityFeeConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 75,
+            min_micro_lamports: 10_000,
+            max_micro_lamports: 1_000_000,
+            escalation_bps: 5_000,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! selects the backend deposits and swaps are submitted through
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SubmitterConfig {
+    /// sendTransaction through the configured rpc
+    #[default]
+    Rpc,
+    /// sendTransaction through the configured rpc and every one of `urls` at once
+    FanOut { urls: Vec<String> },
+    /// sendBundle to a block engine, tipping `tip_lamports` to `tip_account` in every transaction
+    Bundle {
+        url: String,
+        tip_account: String,
+        tip_lamports: u64,
+    },
+    /// sends straight to the tpu ports of the leaders of the next `fanout_slots` slots, over
+    /// quic unless `use_quic` is false. with `with_rpc` the configured rpc's sendTransaction
+    /// is used alongside the tpu
+    Tpu {
+        #[serde(default = "default_fanout_slots")]
+        fanout_slots: u64,
+        #[serde(default = "default_use_quic")]
+        use_quic: bool,
+        #[serde(default)]
+        with_rpc: bool,
+    },
+}
+
+fn default_fanout_slots() -> u64 {
+    solana_client::tpu_client::DEFAULT_FANOUT_SLOTS
+}
+
+fn default_use_quic() -> bool {
+    true
+}
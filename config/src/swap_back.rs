@@ -0,0 +1,121 @@
+//! policies controlling how much jlp auto-deposit swaps back after each deposit
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const BPS_POWER: u128 = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SwapBackPolicy {
+    /// never swap back, accumulating jlp
+    Hold,
+    /// swap back only the jlp minted by deposits made during this run
+    Minted,
+    /// swap back a percentage (0-100) of the jlp balance
+    Percent { percent: u8 },
+    /// swap back the jlp balance only when the market quote is at least
+    /// `min_premium_bps` above the pool's nav
+    Premium { min_premium_bps: u64 },
+}
+
+impl Default for SwapBackPolicy {
+    fn default() -> Self {
+        Self::Percent { percent: 100 }
+    }
+}
+
+impl SwapBackPolicy {
+    /// returns how much of `balance` to swap back, given the jlp minted by this run
+    /// that hasn't been swapped back yet
+    pub fn swap_amount(&self, balance: u64, unswapped_minted: u64) -> u64 {
+        match self {
+            Self::Hold => 0,
+            Self::Minted => unswapped_minted.min(balance),
+            Self::Percent { percent } => {
+                (balance as u128 * (*percent).min(100) as u128 / 100) as u64
+            }
+            Self::Premium { .. } => balance,
+        }
+    }
+    /// returns true if a market quote paying `quoted_out` for jlp worth `nav` at the pool's
+    /// aum is acceptable, both in the same usd denomination
+    pub fn accepts_quote(&self, quoted_out: u64, nav: u128) -> bool {
+        match self {
+            Self::Premium { min_premium_bps } => {
+                quoted_out as u128 * BPS_POWER >= nav * (BPS_POWER + *min_premium_bps as u128)
+            }
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for SwapBackPolicy {
+    type Err = anyhow::Error;
+    /// parses `hold`, `minted`, `percent:<0-100>` or `premium:<bps>`
+    fn from_str(s: &str) -> Result<Self> {
+        let (mode, value) = match s.split_once(':') {
+            Some((mode, value)) => (mode, Some(value)),
+            None => (s, None),
+        };
+        match (mode.to_ascii_lowercase().as_str(), value) {
+            ("hold", None) => Ok(Self::Hold),
+            ("minted", None) => Ok(Self::Minted),
+            ("percent", Some(percent)) => {
+                let percent = percent.parse::<u8>()?;
+                if percent > 100 {
+                    return Err(anyhow!("swap back percent must be between 0 and 100"));
+                }
+                Ok(Self::Percent { percent })
+            }
+            ("premium", Some(bps)) => Ok(Self::Premium {
+                min_premium_bps: bps.parse()?,
+            }),
+            _ => Err(anyhow!(
+                "invalid swap back policy {s}, expected hold, minted, percent:<0-100> or premium:<bps>"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SwapBackPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hold => write!(f, "hold"),
+            Self::Minted => write!(f, "minted"),
+            Self::Percent { percent } => write!(f, "percent:{percent}"),
+            Self::Premium { min_premium_bps } => write!(f, "premium:{min_premium_bps}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_swap_back_policy() {
+        for policy in [
+            SwapBackPolicy::Hold,
+            SwapBackPolicy::Minted,
+            SwapBackPolicy::Percent { percent: 50 },
+            SwapBackPolicy::Premium { min_premium_bps: 25 },
+        ] {
+            assert_eq!(SwapBackPolicy::from_str(&policy.to_string()).unwrap(), policy);
+        }
+        assert!(SwapBackPolicy::from_str("percent:101").is_err());
+        assert!(SwapBackPolicy::from_str("percent").is_err());
+        assert!(SwapBackPolicy::from_str("dump").is_err());
+
+        assert_eq!(SwapBackPolicy::Hold.swap_amount(100, 10), 0);
+        assert_eq!(SwapBackPolicy::Minted.swap_amount(100, 10), 10);
+        assert_eq!(SwapBackPolicy::Minted.swap_amount(5, 10), 5);
+        assert_eq!(SwapBackPolicy::Percent { percent: 25 }.swap_amount(100, 10), 25);
+        assert_eq!(SwapBackPolicy::default().swap_amount(100, 10), 100);
+
+        let premium = SwapBackPolicy::Premium { min_premium_bps: 100 };
+        assert!(premium.accepts_quote(101, 100));
+        assert!(!premium.accepts_quote(100, 100));
+        assert!(SwapBackPolicy::Hold.accepts_quote(0, 100));
+    }
+}
@@ -4,7 +4,9 @@ use base64::engine::general_purpose::STANDARD as b64;
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table_account::AddressLookupTableAccount;
 use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::hash::Hash;
 use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
@@ -92,6 +94,16 @@ impl SwapResponse {
         prio_fee: Option<u64>, 
         cu_limit: Option<u32>,
     ) -> anyhow::Result<solana_sdk::message::v0::Message> {
+        let instructions = self.instructions(prio_fee, cu_limit)?;
+        let luts = self.load_address_lookup_tables(rpc).await?;
+        compile_v0_message(payer, &instructions, &luts, rpc.get_latest_blockhash().await?)
+    }
+    /// returns the compute budget, setup and swap instructions, omitting cleanup
+    pub fn instructions(
+        &self,
+        prio_fee: Option<u64>,
+        cu_limit: Option<u32>,
+    ) -> anyhow::Result<Vec<Instruction>> {
         let num_instructions = usize::from(prio_fee.is_some()) + 
         usize::from(cu_limit.is_some()) +
         self.setup_instructions.len() + 1 ; // 1 = swap tx
@@ -109,18 +121,32 @@ impl SwapResponse {
         instructions.extend_from_slice(&setup_ixs);
         instructions.push(self.swap_instruction.to_instruction()?);
         // omit cleanup
-        let luts = self.address_lookup_tables();
-        let luts = load_address_lookup_table(rpc, &luts).await?;
-        let msg = solana_sdk::message::v0::Message::try_compile(
-            &payer,
-           &instructions,
-            &luts,
-            rpc.get_latest_blockhash().await?
-        )?;
-        Ok(msg)
+        Ok(instructions)
+    }
+    pub async fn load_address_lookup_tables(
+        &self,
+        rpc: &RpcClient,
+    ) -> anyhow::Result<Vec<AddressLookupTableAccount>> {
+        load_address_lookup_table(rpc, &self.address_lookup_tables()).await
     }
 }
 
+/// compiles a v0 message against an explicit blockhash, so the same instructions can be re-signed
+/// once the blockhash expires
+pub fn compile_v0_message(
+    payer: Pubkey,
+    instructions: &[Instruction],
+    luts: &[AddressLookupTableAccount],
+    blockhash: Hash,
+) -> anyhow::Result<solana_sdk::message::v0::Message> {
+    Ok(solana_sdk::message::v0::Message::try_compile(
+        &payer,
+        instructions,
+        luts,
+        blockhash,
+    )?)
+}
+
 impl SetupInstruction {
     pub fn to_instruction(&self) -> anyhow::Result<Instruction> {
         let ix_data = b64.decode(&self.data)?;
@@ -1,50 +1,102 @@
 use std::sync::Arc;
 
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{signature::{Keypair, Signer, Signature}, transaction::VersionedTransaction, message::VersionedMessage};
 use anyhow::{Result, anyhow, Context};
-use crate::swap_types::SwapResponse;
+use solana_sdk::{commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction};
+use tx_sender::{
+    confirmation::TxOutcome,
+    compute_units::ComputeUnitEstimator,
+    priority_fee::{writable_accounts, PriorityFeeEstimator},
+    sender::{Sent, TransactionSender},
+    submitter::{RpcSubmitter, Submitter},
+};
+use crate::swap_types::{compile_v0_message, SwapResponse};
 
 #[derive(Clone)]
 pub struct Swapper {
     pub rpc: Arc<RpcClient>,
-    pub keypair_bytes: [u8; 64]
+    pub keypair_bytes: [u8; 64],
+    pub priority_fee: PriorityFeeEstimator,
+    pub compute_units: ComputeUnitEstimator,
+    pub submitter: Arc<dyn Submitter>,
 }
 
 impl Swapper {
     pub fn new(rpc: Arc<RpcClient>, keypair: Keypair) -> Swapper {
         Self {
+            priority_fee: PriorityFeeEstimator::new(rpc.clone()),
+            compute_units: ComputeUnitEstimator::new(rpc.clone()),
+            submitter: Arc::new(RpcSubmitter::new(rpc.clone())),
             rpc,
             keypair_bytes: keypair.to_bytes(),
         }
     }
+    /// sets how the priority fee of swaps is estimated
+    pub fn with_priority_fee(mut self, priority_fee: PriorityFeeEstimator) -> Self {
+        self.priority_fee = priority_fee;
+        self
+    }
+    /// sets how the compute unit limit of swaps is sized
+    pub fn with_compute_units(mut self, compute_units: ComputeUnitEstimator) -> Self {
+        self.compute_units = compute_units;
+        self
+    }
+    /// sets the backend swaps are submitted through
+    pub fn with_submitter(mut self, submitter: Arc<dyn Submitter>) -> Self {
+        self.submitter = submitter;
+        self
+    }
+    /// sends the swap until it lands, re-signing with a fresh blockhash up to `retries` times
+    /// if it expires, and returns an error unless it landed
     pub async fn new_swap(self: &Arc<Self>, swap_response: SwapResponse, skip_preflight: bool, retries: usize) -> Result<Signature> {
-        let kp = Keypair::from_bytes(&self.keypair_bytes)?;
-        let v0_msg = swap_response.new_v0_transaction(&self.rpc, kp.pubkey(), Some(prio_fee(0.001)), Some(1_000_000)).await?;
-        let v_tx = VersionedTransaction::try_new(
-            VersionedMessage::V0(v0_msg),
-            &vec![&kp]
-        )?;
-        match self.rpc.send_transaction_with_config(
-            &v_tx,
-            RpcSendTransactionConfig {
-                skip_preflight,
-                max_retries: Some(retries),
-                ..Default::default()
-            }
-        ).await {
-            Ok(sig) => {
-                Ok(sig)
-            }
-            Err(err) => return Err(anyhow!("failed execute swap {err:#?}"))
+        let sent = self
+            .send_swap(swap_response, self.rpc.commitment(), skip_preflight, retries)
+            .await?;
+        match sent.outcome {
+            TxOutcome::Landed { .. } => Ok(sent.signature),
+            TxOutcome::Failed { slot, err } => Err(anyhow!("swap {} failed in slot {slot} {err}", sent.signature)),
+            TxOutcome::Rejected { err } => Err(anyhow!("swap rejected by preflight {err}")),
+            TxOutcome::Expired => Err(anyhow!("swap {} expired after {} attempts", sent.signature, sent.attempts)),
+            TxOutcome::Pending => Err(anyhow!("swap {} stopped being tracked before landing", sent.signature)),
         }
     }
+    /// sends the swap until it reaches `commitment`, fails or expires, returning its final state
+    pub async fn send_swap(
+        self: &Arc<Self>,
+        swap_response: SwapResponse,
+        commitment: CommitmentConfig,
+        skip_preflight: bool,
+        retries: usize,
+    ) -> Result<Sent> {
+        let kp = Keypair::from_bytes(&self.keypair_bytes)?;
+        let mut instructions = swap_response.instructions(None, None)?;
+        // the tip account is write locked by every bundle, so it is left out of the fee estimate
+        let fee_accounts = writable_accounts(&instructions);
+        instructions.extend(self.submitter.tip_instructions(&kp.pubkey()));
+        let luts = swap_response.load_address_lookup_tables(&self.rpc).await?;
+        let cu_limit = self.compute_units.limit(kp.pubkey(), &instructions, &luts).await;
+        instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(cu_limit));
+        let sender = TransactionSender::new(self.rpc.clone(), commitment)
+            .with_skip_preflight(skip_preflight)
+            .with_max_resigns(retries)
+            .with_priority_fee(self.priority_fee.clone(), fee_accounts)
+            .with_submitter(self.submitter.clone());
+        sender
+            .send(|attempt| {
+                let mut ixs = vec![ComputeBudgetInstruction::set_compute_unit_price(attempt.priority_fee)];
+                ixs.extend(instructions.iter().cloned());
+                let v0_msg = compile_v0_message(kp.pubkey(), &ixs, &luts, attempt.blockhash)?;
+                Ok(VersionedTransaction::try_new(
+                    VersionedMessage::V0(v0_msg),
+                    &vec![&kp]
+                )?)
+            })
+            .await
+            .with_context(|| "failed to send swap")
+    }
     pub fn keypair(self: &Arc<Self>) -> Keypair {
         // if this fails something fucked up
         Keypair::from_bytes(&self.keypair_bytes).unwrap()
     }
 }
-
-pub fn prio_fee(input: f64) -> u64 {
-    spl_token::ui_amount_to_amount(input, 9)
-}
\ No newline at end of file
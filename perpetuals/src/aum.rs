@@ -0,0 +1,161 @@
+//! recomputes the pool's assets under management off-chain the way getAssetsUnderManagement
+//! does, as the stored aum_usd is only updated when the pool is refreshed
+
+use crate::jlp_cacher::token_to_usd_amount;
+use crate::oracle::CustodyPrice;
+use anyhow::{anyhow, Result};
+
+/// the usd price, scaled by USD_DECIMALS, a custody is valued at. Min and Max take the lower
+/// or higher of the aggregate and ema prices, while Ignore uses the aggregate price alone
+pub fn aum_price(price: &CustodyPrice, mode: &crate::PriceCalcMode) -> Result<u64> {
+    let (spot, ema) = (price.price.usd()?, price.ema_price.usd()?);
+    Ok(match mode {
+        crate::PriceCalcMode::Min => spot.min(ema),
+        crate::PriceCalcMode::Max => spot.max(ema),
+        crate::PriceCalcMode::Ignore => spot,
+    })
+}
+
+/// unrealized pnl of every short against the custody at `price`, scaled by USD_DECIMALS.
+/// positive when the traders are in profit, which the pool owes
+pub fn short_pnl_usd(custody: &crate::Custody, price: u64) -> i128 {
+    let average_price = custody.assets.global_short_average_prices as i128;
+    if average_price == 0 {
+        return 0;
+    }
+    custody.assets.global_short_sizes as i128 * (average_price - price as i128) / average_price
+}
+
+/// the custody's share of aum at `price`, scaled by USD_DECIMALS. tokens locked by longs are
+/// valued at the guaranteed usd of the positions they back, and the pool owes short profits
+pub fn custody_aum_usd(custody: &crate::Custody, price: u64) -> i128 {
+    let owned_usd = token_to_usd_amount(custody.assets.owned, price, custody.decimals);
+    if custody.is_stable {
+        return owned_usd as i128;
+    }
+    let locked_usd = token_to_usd_amount(custody.assets.locked, price, custody.decimals);
+    custody.assets.guaranteed_usd as i128 + owned_usd.saturating_sub(locked_usd) as i128
+        - short_pnl_usd(custody, price)
+}
+
+/// returns the pool's aum, scaled by USD_DECIMALS, from every custody and its prices
+pub fn assets_under_management_usd(
+    custodies: &[crate::Custody],
+    prices: &[CustodyPrice],
+    mode: &crate::PriceCalcMode,
+) -> Result<u128> {
+    if custodies.len() != prices.len() {
+        return Err(anyhow!(
+            "{} custodies but {} prices",
+            custodies.len(),
+            prices.len()
+        ));
+    }
+    let mut aum_usd = 0_i128;
+    for (custody, price) in custodies.iter().zip(prices) {
+        aum_usd += custody_aum_usd(custody, aum_price(price, mode)?);
+    }
+    Ok(aum_usd.max(0) as u128)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::oracle::OraclePrice;
+    fn custody(is_stable: bool, decimals: u8, assets: crate::Assets) -> crate::Custody {
+        crate::Custody {
+            pool: Default::default(),
+            mint: Default::default(),
+            token_account: Default::default(),
+            decimals,
+            is_stable,
+            oracle: crate::OracleParams {
+                oracle_account: Default::default(),
+                oracle_type: crate::OracleType::Pyth,
+                max_price_error: 100,
+                max_price_age_sec: 60,
+            },
+            pricing: crate::PricingParams {
+                trade_spread_long: 0,
+                trade_spread_short: 0,
+                swap_spread: 0,
+                max_leverage: 0,
+                max_global_long_sizes: 0,
+                max_global_short_sizes: 0,
+            },
+            permissions: crate::Permissions {
+                allow_swap: true,
+                allow_add_liquidity: true,
+                allow_remove_liquidity: true,
+                allow_increase_position: true,
+                allow_decrease_position: true,
+                allow_collateral_withdrawal: true,
+                allow_liquidate_position: true,
+            },
+            target_ratio_bps: 5_000,
+            assets,
+            funding_rate_state: crate::FundingRateState {
+                cumulative_interest_rate: 0,
+                last_update: 0,
+                hourly_funding_bps: 0,
+            },
+            bump: 0,
+            token_account_bump: 0,
+        }
+    }
+    fn price(spot: u64, ema: u64) -> CustodyPrice {
+        let price = |price| OraclePrice {
+            price,
+            exponent: -8,
+            conf: 0,
+            publish_time: 0,
+        };
+        CustodyPrice {
+            price: price(spot),
+            ema_price: price(ema),
+        }
+    }
+    #[test]
+    fn test_assets_under_management() {
+        // 10 sol, 4 locked by longs guaranteeing $300, and $200 of shorts opened at $125
+        let sol = custody(
+            false,
+            9,
+            crate::Assets {
+                fees_reserves: 0,
+                owned: 10_000_000_000,
+                locked: 4_000_000_000,
+                guaranteed_usd: 300_000_000,
+                global_short_sizes: 200_000_000,
+                global_short_average_prices: 125_000_000,
+            },
+        );
+        let usdc = custody(
+            true,
+            6,
+            crate::Assets {
+                fees_reserves: 0,
+                owned: 1_000_000_000,
+                locked: 250_000_000,
+                guaranteed_usd: 0,
+                global_short_sizes: 0,
+                global_short_average_prices: 0,
+            },
+        );
+        // shorts are $40 in profit at $100
+        assert_eq!(short_pnl_usd(&sol, 100_000_000), 40_000_000);
+        assert_eq!(short_pnl_usd(&sol, 150_000_000), -40_000_000);
+        // $300 guaranteed + 6 unlocked sol at $100 - $40 short profit
+        assert_eq!(custody_aum_usd(&sol, 100_000_000), 860_000_000);
+        assert_eq!(custody_aum_usd(&usdc, 1_000_000), 1_000_000_000);
+
+        let custodies = [sol, usdc];
+        let prices = [price(10_000_000_000, 9_000_000_000), price(100_000_000, 99_990_000)];
+        let aum = |mode| assets_under_management_usd(&custodies, &prices, &mode).unwrap();
+        assert_eq!(aum(crate::PriceCalcMode::Ignore), 1_860_000_000);
+        assert_eq!(aum(crate::PriceCalcMode::Max), 1_860_000_000);
+        // $300 + 6 sol at $90 - $56 short profit, usdc at $0.9999
+        assert_eq!(aum(crate::PriceCalcMode::Min), 784_000_000 + 999_900_000);
+        assert!(assets_under_management_usd(&custodies, &prices[..1], &crate::PriceCalcMode::Max).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! tracks the pool's aum against its aum cap over time, detecting when governance raises the
+//! cap and how quickly the room it opens is filled by deposits
+
+use crate::jlp_cacher::{usd_to_ui, BPS_POWER};
+use std::collections::VecDeque;
+
+/// an opening counts as filled once less than this share of its room remains
+const UNFILLED_BPS: u128 = 100;
+
+/// the pool's aum and aum cap, both scaled by USD_DECIMALS, observed at a slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacitySample {
+    pub slot: u64,
+    /// unix timestamp in seconds at which the sample was observed
+    pub timestamp: i64,
+    pub aum_usd: u128,
+    pub max_aum_usd: u128,
+}
+
+impl CapacitySample {
+    pub fn from_pool(slot: u64, timestamp: i64, pool: &crate::Pool) -> Self {
+        Self {
+            slot,
+            timestamp,
+            aum_usd: pool.aum_usd,
+            max_aum_usd: pool.limit.max_aum_usd,
+        }
+    }
+    /// usd that can be deposited before the pool hits its cap
+    pub fn room_usd(&self) -> u128 {
+        self.max_aum_usd.saturating_sub(self.aum_usd)
+    }
+}
+
+/// room opened by a raise of the aum cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityOpening {
+    /// first slot the raised cap was observed at
+    pub slot: u64,
+    pub timestamp: i64,
+    pub previous_max_aum_usd: u128,
+    pub max_aum_usd: u128,
+    /// room added by the raise, scaled by USD_DECIMALS. less than the raise itself when the
+    /// pool's aum was over the previous cap
+    pub room_usd: u128,
+    /// all room available once the cap was raised, including any left before it, which the
+    /// opening is filled against
+    pub total_room_usd: u128,
+    /// slot and timestamp the room was observed filled at
+    pub filled: Option<(u64, i64)>,
+}
+
+impl CapacityOpening {
+    pub fn fill_slots(&self) -> Option<u64> {
+        self.filled.map(|(slot, _)| slot.saturating_sub(self.slot))
+    }
+    pub fn fill_secs(&self) -> Option<i64> {
+        self.filled
+            .map(|(_, timestamp)| timestamp.saturating_sub(self.timestamp).max(0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityEvent {
+    /// the cap was raised, opening room for deposits
+    Raised(CapacityOpening),
+    /// the room opened by a raise was filled
+    Filled(CapacityOpening),
+}
+
+/// keeps a bounded history of samples and the opening currently being filled
+pub struct CapacityTracker {
+    history: VecDeque<CapacitySample>,
+    max_history: usize,
+    open: Option<CapacityOpening>,
+}
+
+impl CapacityTracker {
+    pub fn new(max_history: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(max_history),
+            max_history: max_history.max(1),
+            open: None,
+        }
+    }
+    /// records a sample, returning the events it triggered. samples that aren't newer than
+    /// the last one recorded are ignored, as the pool watcher may observe a slot twice
+    pub fn record(&mut self, sample: CapacitySample) -> Vec<CapacityEvent> {
+        let previous = match self.history.back() {
+            Some(previous) if sample.slot <= previous.slot => return vec![],
+            previous => previous.copied(),
+        };
+        if self.history.len() == self.max_history {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+
+        let mut events = vec![];
+        if let Some(previous) = previous {
+            if sample.max_aum_usd > previous.max_aum_usd {
+                // a raise before the last opening filled supersedes it
+                let opening = CapacityOpening {
+                    slot: sample.slot,
+                    timestamp: sample.timestamp,
+                    previous_max_aum_usd: previous.max_aum_usd,
+                    max_aum_usd: sample.max_aum_usd,
+                    room_usd: (sample.max_aum_usd - previous.max_aum_usd).min(sample.room_usd()),
+                    total_room_usd: sample.room_usd(),
+                    filled: None,
+                };
+                self.open = Some(opening);
+                events.push(CapacityEvent::Raised(opening));
+            }
+        }
+        if let Some(mut opening) = self.open {
+            if sample.room_usd() * BPS_POWER as u128 <= opening.total_room_usd * UNFILLED_BPS {
+                opening.filled = Some((sample.slot, sample.timestamp));
+                self.open = None;
+                events.push(CapacityEvent::Filled(opening));
+            }
+        }
+        events
+    }
+    /// the opening that hasn't been filled yet, if any
+    pub fn open(&self) -> Option<&CapacityOpening> {
+        self.open.as_ref()
+    }
+    /// recorded samples, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &CapacitySample> {
+        self.history.iter()
+    }
+}
+
+/// how fast past openings were filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FillSummary {
+    pub openings: usize,
+    pub filled: usize,
+    /// room opened across all openings, scaled by USD_DECIMALS
+    pub total_room_usd: u128,
+    pub fastest_fill_secs: Option<i64>,
+    pub median_fill_secs: Option<i64>,
+    pub slowest_fill_secs: Option<i64>,
+    pub median_fill_slots: Option<u64>,
+}
+
+impl FillSummary {
+    pub fn new(openings: &[CapacityOpening]) -> Self {
+        let mut secs = openings.iter().filter_map(|o| o.fill_secs()).collect::<Vec<_>>();
+        let mut slots = openings.iter().filter_map(|o| o.fill_slots()).collect::<Vec<_>>();
+        secs.sort_unstable();
+        slots.sort_unstable();
+        Self {
+            openings: openings.len(),
+            filled: secs.len(),
+            total_room_usd: openings.iter().map(|o| o.room_usd).sum(),
+            fastest_fill_secs: secs.first().copied(),
+            median_fill_secs: secs.get(secs.len() / 2).copied(),
+            slowest_fill_secs: secs.last().copied(),
+            median_fill_slots: slots.get(slots.len() / 2).copied(),
+        }
+    }
+}
+
+impl std::fmt::Display for FillSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = |secs: Option<i64>| secs.map(|s| format!("{s}s")).unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "openings={} filled={} total_room_usd={:.2} fastest_fill={} median_fill={} slowest_fill={} median_fill_slots={}",
+            self.openings,
+            self.filled,
+            usd_to_ui(self.total_room_usd),
+            secs(self.fastest_fill_secs),
+            secs(self.median_fill_secs),
+            secs(self.slowest_fill_secs),
+            self.median_fill_slots
+                .map(|slots| slots.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn sample(slot: u64, aum_usd: u128, max_aum_usd: u128) -> CapacitySample {
+        CapacitySample {
+            slot,
+            timestamp: slot as i64 * 2,
+            aum_usd,
+            max_aum_usd,
+        }
+    }
+    #[test]
+    fn test_capacity_tracker() {
+        let mut tracker = CapacityTracker::new(3);
+        assert!(tracker.record(sample(10, 1_000, 1_000)).is_empty());
+        // aum moving under an unchanged cap isn't an opening
+        assert!(tracker.record(sample(11, 990, 1_000)).is_empty());
+        let raised = match tracker.record(sample(12, 1_000, 2_000))[..] {
+            [CapacityEvent::Raised(opening)] => opening,
+            ref events => panic!("unexpected events {events:?}"),
+        };
+        assert_eq!(raised.slot, 12);
+        assert_eq!(raised.previous_max_aum_usd, 1_000);
+        assert_eq!(raised.room_usd, 1_000);
+        // stale and repeated slots are ignored
+        assert!(tracker.record(sample(12, 2_000, 2_000)).is_empty());
+        assert!(tracker.record(sample(13, 1_500, 2_000)).is_empty());
+        assert_eq!(tracker.open(), Some(&raised));
+        let filled = match tracker.record(sample(20, 1_995, 2_000))[..] {
+            [CapacityEvent::Filled(opening)] => opening,
+            ref events => panic!("unexpected events {events:?}"),
+        };
+        assert_eq!(filled.fill_slots(), Some(8));
+        assert_eq!(filled.fill_secs(), Some(16));
+        assert!(tracker.open().is_none());
+        assert_eq!(tracker.history().map(|s| s.slot).collect::<Vec<_>>(), vec![12, 13, 20]);
+        // a raise that is filled within the slot it was observed at
+        assert_eq!(tracker.record(sample(21, 3_000, 3_000)).len(), 2);
+        // room left before a raise isn't counted as opened by it, but has to be filled too
+        assert!(tracker.record(sample(22, 2_500, 3_000)).is_empty());
+        let raised = match tracker.record(sample(23, 2_500, 3_200))[..] {
+            [CapacityEvent::Raised(opening)] => opening,
+            ref events => panic!("unexpected events {events:?}"),
+        };
+        assert_eq!(raised.room_usd, 200);
+        assert_eq!(raised.total_room_usd, 700);
+        assert!(tracker.record(sample(24, 3_000, 3_200)).is_empty());
+        assert_eq!(tracker.record(sample(25, 3_195, 3_200)).len(), 1);
+    }
+    #[test]
+    fn test_fill_summary() {
+        let opening = |slot: u64, room_usd: u128, fill_slots: Option<u64>| CapacityOpening {
+            slot,
+            timestamp: slot as i64,
+            previous_max_aum_usd: 0,
+            max_aum_usd: room_usd,
+            room_usd,
+            total_room_usd: room_usd,
+            filled: fill_slots.map(|fill| (slot + fill, (slot + fill) as i64)),
+        };
+        assert_eq!(FillSummary::new(&[]), FillSummary::default());
+        let summary = FillSummary::new(&[
+            opening(100, 5_000_000, Some(30)),
+            opening(200, 1_000_000, Some(4)),
+            opening(300, 2_000_000, None),
+            opening(400, 2_000_000, Some(600)),
+        ]);
+        assert_eq!(summary.openings, 4);
+        assert_eq!(summary.filled, 3);
+        assert_eq!(summary.total_room_usd, 10_000_000);
+        assert_eq!(summary.fastest_fill_secs, Some(4));
+        assert_eq!(summary.median_fill_secs, Some(30));
+        assert_eq!(summary.slowest_fill_secs, Some(600));
+        assert_eq!(summary.median_fill_slots, Some(30));
+    }
+}
@@ -0,0 +1,469 @@
+//! decodes the events the perpetuals program emits, either as self-cpi instructions routed
+//! through its event authority or as `Program data:` log lines, so the effect of a landed
+//! transaction can be read from what the program reports rather than from balance changes
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose, Engine};
+use solana_sdk::bs58;
+use solana_transaction_status::{
+    EncodedTransactionWithStatusMeta, UiInnerInstructions, UiInstruction, UiLoadedAddresses,
+};
+use std::str::FromStr;
+
+/// prefixes the data of every self-cpi event instruction, sha256("anchor:event")[..8] as
+/// little endian bytes
+pub const EVENT_IX_TAG: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+const PROGRAM_DATA: &str = "Program data: ";
+
+/// every event the idl defines, named as in the idl
+pub const EVENT_NAMES: [&str; 12] = [
+    "CreatePositionRequestEvent",
+    "ClosePositionRequestEvent",
+    "IncreasePositionEvent",
+    "IncreasePositionPreSwapEvent",
+    "DecreasePositionEvent",
+    "DecreasePositionPostSwapEvent",
+    "LiquidatePositionEvent",
+    "LiquidateFullPositionEvent",
+    "PoolSwapEvent",
+    "PoolSwapExactOutEvent",
+    "AddLiquidityEvent",
+    "RemoveLiquidityEvent",
+];
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CreatePositionRequestEvent {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub position_key: Pubkey,
+    pub position_side: u8,
+    pub position_mint: Pubkey,
+    pub position_custody: Pubkey,
+    pub position_collateral_mint: Pubkey,
+    pub position_collateral_custody: Pubkey,
+    pub position_request_key: Pubkey,
+    pub position_request_mint: Pubkey,
+    pub size_usd_delta: u64,
+    pub collateral_delta: u64,
+    pub price_slippage: Option<u64>,
+    pub jupiter_minimum_out: Option<u64>,
+    pub pre_swap_amount: Option<u64>,
+    pub request_change: u8,
+    pub open_time: i64,
+    pub referral: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ClosePositionRequestEvent {
+    pub position_request_key: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IncreasePositionEvent {
+    pub position_key: Pubkey,
+    pub position_side: u8,
+    pub position_custody: Pubkey,
+    pub position_collateral_custody: Pubkey,
+    pub position_size_usd: u64,
+    pub position_mint: Pubkey,
+    pub position_request_key: Pubkey,
+    pub position_request_mint: Pubkey,
+    pub position_request_change: u8,
+    pub position_request_type: u8,
+    pub position_request_collateral_delta: u64,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub size_usd_delta: u64,
+    pub collateral_usd_delta: u64,
+    pub collateral_token_delta: u64,
+    pub price: u64,
+    pub price_slippage: Option<u64>,
+    pub fee_token: u64,
+    pub fee_usd: u64,
+    pub open_time: i64,
+    pub referral: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IncreasePositionPreSwapEvent {
+    pub position_request_key: Pubkey,
+    pub transfer_amount: u64,
+    pub collateral_custody_pre_swap_amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DecreasePositionEvent {
+    pub position_key: Pubkey,
+    pub position_side: u8,
+    pub position_custody: Pubkey,
+    pub position_collateral_custody: Pubkey,
+    pub position_size_usd: u64,
+    pub position_mint: Pubkey,
+    pub position_request_key: Pubkey,
+    pub position_request_mint: Pubkey,
+    pub position_request_change: u8,
+    pub position_request_type: u8,
+    pub has_profit: bool,
+    pub pnl_delta: u64,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub size_usd_delta: u64,
+    pub transfer_amount_usd: u64,
+    pub transfer_token: Option<u64>,
+    pub price: u64,
+    pub price_slippage: Option<u64>,
+    pub fee_usd: u64,
+    pub open_time: i64,
+    pub referral: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DecreasePositionPostSwapEvent {
+    pub position_request_key: Pubkey,
+    pub swap_amount: u64,
+    pub jupiter_minimum_out: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LiquidatePositionEvent {
+    pub position_key: Pubkey,
+    pub position_side: u8,
+    pub position_custody: Pubkey,
+    pub position_collateral_custody: Pubkey,
+    pub position_collateral_mint: Pubkey,
+    pub position_mint: Pubkey,
+    pub position_size_usd: u64,
+    pub has_profit: bool,
+    pub pnl_delta: u64,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub transfer_amount_usd: u64,
+    pub transfer_token: u64,
+    pub price: u64,
+    pub fee_usd: u64,
+    pub open_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LiquidateFullPositionEvent {
+    pub position_key: Pubkey,
+    pub position_side: u8,
+    pub position_custody: Pubkey,
+    pub position_collateral_custody: Pubkey,
+    pub position_collateral_mint: Pubkey,
+    pub position_mint: Pubkey,
+    pub position_size_usd: u64,
+    pub has_profit: bool,
+    pub pnl_delta: u64,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub transfer_amount_usd: u64,
+    pub transfer_token: u64,
+    pub price: u64,
+    pub fee_usd: u64,
+    pub liquidation_fee_usd: u64,
+    pub open_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PoolSwapEvent {
+    pub receiving_custody_key: Pubkey,
+    pub dispensing_custody_key: Pubkey,
+    pub pool_key: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub swap_usd_amount: u64,
+    pub amount_out_after_fees: u64,
+    pub fee_bps: u64,
+    pub owner_key: Pubkey,
+    pub receiving_account_key: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PoolSwapExactOutEvent {
+    pub receiving_custody_key: Pubkey,
+    pub dispensing_custody_key: Pubkey,
+    pub pool_key: Pubkey,
+    pub amount_in: u64,
+    pub amount_in_after_fees: u64,
+    pub amount_out: u64,
+    pub swap_usd_amount: u64,
+    pub fee_bps: u64,
+    pub owner_key: Pubkey,
+    pub receiving_account_key: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AddLiquidityEvent {
+    pub custody_key: Pubkey,
+    pub pool_key: Pubkey,
+    pub token_amount_in: u64,
+    pub pre_pool_amount_usd: u128,
+    pub token_amount_usd: u64,
+    pub fee_bps: u64,
+    pub token_amount_after_fee: u64,
+    pub mint_amount_usd: u64,
+    pub lp_amount: u64,
+    pub post_pool_amount_usd: u128,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RemoveLiquidityEvent {
+    pub custody_key: Pubkey,
+    pub pool_key: Pubkey,
+    pub lp_amount_in: u64,
+    pub remove_amount_usd: u64,
+    pub fee_bps: u64,
+    pub remove_token_amount: u64,
+    pub token_amount_after_fee: u64,
+    pub post_pool_amount_usd: u128,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PerpetualsEvent {
+    CreatePositionRequest(CreatePositionRequestEvent),
+    ClosePositionRequest(ClosePositionRequestEvent),
+    IncreasePosition(IncreasePositionEvent),
+    IncreasePositionPreSwap(IncreasePositionPreSwapEvent),
+    DecreasePosition(DecreasePositionEvent),
+    DecreasePositionPostSwap(DecreasePositionPostSwapEvent),
+    LiquidatePosition(LiquidatePositionEvent),
+    LiquidateFullPosition(LiquidateFullPositionEvent),
+    PoolSwap(PoolSwapEvent),
+    PoolSwapExactOut(PoolSwapExactOutEvent),
+    AddLiquidity(AddLiquidityEvent),
+    RemoveLiquidity(RemoveLiquidityEvent),
+}
+
+impl PerpetualsEvent {
+    /// decodes an event from its discriminator followed by its borsh encoded fields,
+    /// returning None for discriminators of events the idl doesn't define
+    pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+        if data.len() < 8 {
+            return Err(anyhow!("event data too short, {} bytes", data.len()));
+        }
+        let (disc, mut data) = data.split_at(8);
+        let Some(name) = EVENT_NAMES.iter().find(|name| discriminator(name) == disc) else {
+            return Ok(None);
+        };
+        Ok(Some(match *name {
+            "CreatePositionRequestEvent" => Self::CreatePositionRequest(CreatePositionRequestEvent::deserialize(&mut data)?),
+            "ClosePositionRequestEvent" => Self::ClosePositionRequest(ClosePositionRequestEvent::deserialize(&mut data)?),
+            "IncreasePositionEvent" => Self::IncreasePosition(IncreasePositionEvent::deserialize(&mut data)?),
+            "IncreasePositionPreSwapEvent" => Self::IncreasePositionPreSwap(IncreasePositionPreSwapEvent::deserialize(&mut data)?),
+            "DecreasePositionEvent" => Self::DecreasePosition(DecreasePositionEvent::deserialize(&mut data)?),
+            "DecreasePositionPostSwapEvent" => Self::DecreasePositionPostSwap(DecreasePositionPostSwapEvent::deserialize(&mut data)?),
+            "LiquidatePositionEvent" => Self::LiquidatePosition(LiquidatePositionEvent::deserialize(&mut data)?),
+            "LiquidateFullPositionEvent" => Self::LiquidateFullPosition(LiquidateFullPositionEvent::deserialize(&mut data)?),
+            "PoolSwapEvent" => Self::PoolSwap(PoolSwapEvent::deserialize(&mut data)?),
+            "PoolSwapExactOutEvent" => Self::PoolSwapExactOut(PoolSwapExactOutEvent::deserialize(&mut data)?),
+            "AddLiquidityEvent" => Self::AddLiquidity(AddLiquidityEvent::deserialize(&mut data)?),
+            "RemoveLiquidityEvent" => Self::RemoveLiquidity(RemoveLiquidityEvent::deserialize(&mut data)?),
+            _ => unreachable!(),
+        }))
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CreatePositionRequest(_) => "CreatePositionRequestEvent",
+            Self::ClosePositionRequest(_) => "ClosePositionRequestEvent",
+            Self::IncreasePosition(_) => "IncreasePositionEvent",
+            Self::IncreasePositionPreSwap(_) => "IncreasePositionPreSwapEvent",
+            Self::DecreasePosition(_) => "DecreasePositionEvent",
+            Self::DecreasePositionPostSwap(_) => "DecreasePositionPostSwapEvent",
+            Self::LiquidatePosition(_) => "LiquidatePositionEvent",
+            Self::LiquidateFullPosition(_) => "LiquidateFullPositionEvent",
+            Self::PoolSwap(_) => "PoolSwapEvent",
+            Self::PoolSwapExactOut(_) => "PoolSwapExactOutEvent",
+            Self::AddLiquidity(_) => "AddLiquidityEvent",
+            Self::RemoveLiquidity(_) => "RemoveLiquidityEvent",
+        }
+    }
+}
+
+/// sha256("event:<name>")[..8], prefixing the event's fields wherever it is emitted
+pub fn discriminator(name: &str) -> [u8; 8] {
+    hash(format!("event:{name}").as_bytes()).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+/// decodes the `Program data:` lines logged while the perpetuals program itself was executing,
+/// skipping those of any program it invokes
+pub fn parse_log_messages(logs: &[String]) -> Result<Vec<PerpetualsEvent>> {
+    let program_id = crate::ID.to_string();
+    let mut invocations: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+    for log in logs {
+        let Some(message) = log.strip_prefix("Program ") else {
+            continue;
+        };
+        if let Some(data) = log.strip_prefix(PROGRAM_DATA) {
+            if invocations.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+            let data = general_purpose::STANDARD
+                .decode(data)
+                .with_context(|| format!("invalid program data {data}"))?;
+            events.extend(PerpetualsEvent::decode(&data)?);
+            continue;
+        }
+        let mut words = message.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(program), Some("invoke")) => invocations.push(program),
+            (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                invocations.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+/// decodes the self-cpi event instructions among a transaction's inner instructions, with
+/// `account_keys` holding the message's static keys followed by any loaded addresses
+pub fn parse_cpi_instructions(
+    account_keys: &[Pubkey],
+    inner_instructions: &[UiInnerInstructions],
+) -> Result<Vec<PerpetualsEvent>> {
+    let mut events = Vec::new();
+    for ix in inner_instructions.iter().flat_map(|inner| &inner.instructions) {
+        let UiInstruction::Compiled(ix) = ix else {
+            continue;
+        };
+        if account_keys.get(ix.program_id_index as usize) != Some(&crate::ID) {
+            continue;
+        }
+        let data = bs58::decode(&ix.data)
+            .into_vec()
+            .with_context(|| format!("invalid instruction data {}", ix.data))?;
+        if let Some(data) = data.strip_prefix(&EVENT_IX_TAG) {
+            events.extend(PerpetualsEvent::decode(data)?);
+        }
+    }
+    Ok(events)
+}
+
+/// returns every event a fetched transaction emitted. events are read from the self-cpi
+/// instructions when there are any, as logs may be truncated, otherwise from the logs
+pub fn parse_transaction(tx: &EncodedTransactionWithStatusMeta) -> Result<Vec<PerpetualsEvent>> {
+    let meta = tx
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("transaction has no status meta"))?;
+    let inner_instructions: Option<Vec<UiInnerInstructions>> =
+        meta.inner_instructions.clone().into();
+    let mut events = Vec::new();
+    if let Some(inner_instructions) = inner_instructions {
+        let decoded = tx
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("failed to decode transaction, expected a binary encoding"))?;
+        let mut account_keys = decoded.message.static_account_keys().to_vec();
+        let loaded: Option<UiLoadedAddresses> = meta.loaded_addresses.clone().into();
+        if let Some(loaded) = loaded {
+            for key in loaded.writable.iter().chain(&loaded.readonly) {
+                account_keys.push(Pubkey::from_str(key)?);
+            }
+        }
+        events = parse_cpi_instructions(&account_keys, &inner_instructions)?;
+    }
+    if events.is_empty() {
+        let logs: Option<Vec<String>> = meta.log_messages.clone().into();
+        events = parse_log_messages(&logs.unwrap_or_default())?;
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_transaction_status::UiCompiledInstruction;
+    fn add_liquidity_event() -> AddLiquidityEvent {
+        AddLiquidityEvent {
+            custody_key: Pubkey::new_unique(),
+            pool_key: Pubkey::new_unique(),
+            token_amount_in: 1_000_000_000,
+            pre_pool_amount_usd: 1_000_000_000_000,
+            token_amount_usd: 150_000_000,
+            fee_bps: 21,
+            token_amount_after_fee: 997_900_000,
+            mint_amount_usd: 149_685_000,
+            lp_amount: 48_123_456,
+            post_pool_amount_usd: 1_000_149_685_000,
+        }
+    }
+    fn event_data(event: &AddLiquidityEvent) -> Vec<u8> {
+        let mut data = discriminator("AddLiquidityEvent").to_vec();
+        data.extend(event.try_to_vec().unwrap());
+        data
+    }
+    #[test]
+    fn test_decode() {
+        // the discriminator anchor derives for AddLiquidityEvent
+        assert_eq!(
+            discriminator("AddLiquidityEvent"),
+            [27, 178, 153, 186, 47, 196, 140, 45]
+        );
+        let event = add_liquidity_event();
+        assert_eq!(
+            PerpetualsEvent::decode(&event_data(&event)).unwrap(),
+            Some(PerpetualsEvent::AddLiquidity(event.clone()))
+        );
+        assert_eq!(PerpetualsEvent::decode(&[0; 16]).unwrap(), None);
+        assert!(PerpetualsEvent::decode(&event_data(&event)[..20]).is_err());
+    }
+    #[test]
+    fn test_parse_log_messages() {
+        let event = add_liquidity_event();
+        let data = general_purpose::STANDARD.encode(event_data(&event));
+        let token_program = spl_token::ID.to_string();
+        let logs = [
+            format!("Program {} invoke [1]", crate::ID),
+            "Program log: Instruction: AddLiquidity2".to_string(),
+            format!("Program {token_program} invoke [2]"),
+            // logged by the token program, not the perpetuals program
+            format!("{PROGRAM_DATA}{data}"),
+            format!("Program {token_program} success"),
+            format!("{PROGRAM_DATA}{data}"),
+            format!("Program {} consumed 90000 of 200000 compute units", crate::ID),
+            format!("Program {} success", crate::ID),
+        ];
+        assert_eq!(
+            parse_log_messages(&logs).unwrap(),
+            vec![PerpetualsEvent::AddLiquidity(event)]
+        );
+    }
+    #[test]
+    fn test_parse_cpi_instructions() {
+        let event = add_liquidity_event();
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend(event_data(&event));
+        let instruction = |program_id_index, data: &[u8]| {
+            UiInstruction::Compiled(UiCompiledInstruction {
+                program_id_index,
+                accounts: vec![],
+                data: bs58::encode(data).into_string(),
+                stack_height: Some(2),
+            })
+        };
+        let account_keys = [Pubkey::new_unique(), spl_token::ID, crate::ID];
+        let inner_instructions = [UiInnerInstructions {
+            index: 1,
+            instructions: vec![
+                instruction(1, &[3, 0, 0, 0]),
+                instruction(2, &data),
+                // the program's own instructions aren't events
+                instruction(2, &event_data(&event)),
+            ],
+        }];
+        assert_eq!(
+            parse_cpi_instructions(&account_keys, &inner_instructions).unwrap(),
+            vec![PerpetualsEvent::AddLiquidity(event)]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! models the fee charged for adding or removing liquidity through a custody: the pool's base
+//! fee, plus a tax when the custody moves away from its target weight or less a rebate when
+//! it moves towards it
+
+use crate::aum::{aum_price, custody_aum_usd};
+use crate::jlp_cacher::BPS_POWER;
+use crate::oracle::CustodyPrice;
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeModel {
+    /// add_remove_liquidity_bps
+    pub base_bps: u64,
+    /// tax_bps, charged on volatile custodies
+    pub tax_bps: u64,
+    /// stable_swap_tax_bps, charged on stable custodies
+    pub stable_tax_bps: u64,
+}
+
+/// a custody's value and target, as used to weigh it against the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustodyWeight {
+    pub is_stable: bool,
+    pub target_ratio_bps: u64,
+    /// scaled by USD_DECIMALS
+    pub value_usd: u128,
+}
+
+/// the predicted fee for adding or removing `amount_usd` through a custody
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidityFee {
+    pub fee_bps: u64,
+    /// scaled by USD_DECIMALS
+    pub fee_usd: u128,
+    pub current_weight_bps: u64,
+    pub next_weight_bps: u64,
+}
+
+impl FeeModel {
+    pub fn new(fees: &crate::Fees) -> Self {
+        Self {
+            base_bps: fees.add_remove_liquidity_bps,
+            tax_bps: fees.tax_bps,
+            stable_tax_bps: fees.stable_swap_tax_bps,
+        }
+    }
+    /// returns the fee for adding (`increase`) or removing `amount_usd` through `custody` in a
+    /// pool worth `pool_usd`, all usd amounts scaled by USD_DECIMALS. the tax grows with the
+    /// average distance from target over the deposit, capped at the target itself, while the
+    /// rebate for moving towards target is based on the distance before it
+    pub fn liquidity_fee(
+        &self,
+        custody: &CustodyWeight,
+        pool_usd: u128,
+        amount_usd: u128,
+        increase: bool,
+    ) -> LiquidityFee {
+        let tax_bps = if custody.is_stable {
+            self.stable_tax_bps
+        } else {
+            self.tax_bps
+        } as u128;
+        let target = custody.target_ratio_bps as u128;
+        let current = weight_bps(custody.value_usd, pool_usd);
+        let next = if increase {
+            weight_bps(custody.value_usd + amount_usd, pool_usd + amount_usd)
+        } else {
+            weight_bps(
+                custody.value_usd.saturating_sub(amount_usd),
+                pool_usd.saturating_sub(amount_usd),
+            )
+        };
+        let (current_diff, next_diff) = (current.abs_diff(target), next.abs_diff(target));
+        let fee_bps = if target == 0 {
+            self.base_bps as u128 + tax_bps
+        } else if next_diff < current_diff {
+            let rebate_bps = tax_bps * current_diff / target;
+            (self.base_bps as u128).saturating_sub(rebate_bps)
+        } else {
+            let average_diff = ((current_diff + next_diff) / 2).min(target);
+            self.base_bps as u128 + tax_bps * average_diff / target
+        };
+        LiquidityFee {
+            fee_bps: fee_bps as u64,
+            fee_usd: amount_usd * fee_bps / BPS_POWER as u128,
+            current_weight_bps: current as u64,
+            next_weight_bps: next as u64,
+        }
+    }
+}
+
+/// predicts the fee for adding or removing `amount_usd` through each custody, in the same
+/// order as `custodies`. custodies are valued the way the program values the pool for the
+/// operation, at the higher price for deposits and the lower for withdrawals
+pub fn liquidity_fees(
+    fees: &crate::Fees,
+    custodies: &[crate::Custody],
+    prices: &[CustodyPrice],
+    amount_usd: u128,
+    increase: bool,
+) -> Result<Vec<LiquidityFee>> {
+    if custodies.len() != prices.len() {
+        return Err(anyhow!(
+            "{} custodies but {} prices",
+            custodies.len(),
+            prices.len()
+        ));
+    }
+    let mode = if increase {
+        crate::PriceCalcMode::Max
+    } else {
+        crate::PriceCalcMode::Min
+    };
+    let mut weights = Vec::with_capacity(custodies.len());
+    for (custody, price) in custodies.iter().zip(prices) {
+        weights.push(CustodyWeight {
+            is_stable: custody.is_stable,
+            target_ratio_bps: custody.target_ratio_bps,
+            value_usd: custody_aum_usd(custody, aum_price(price, &mode)?).max(0) as u128,
+        });
+    }
+    let pool_usd = weights.iter().map(|weight| weight.value_usd).sum();
+    let model = FeeModel::new(fees);
+    Ok(weights
+        .iter()
+        .map(|weight| model.liquidity_fee(weight, pool_usd, amount_usd, increase))
+        .collect())
+}
+
+fn weight_bps(value_usd: u128, pool_usd: u128) -> u128 {
+    if pool_usd == 0 {
+        return 0;
+    }
+    value_usd * BPS_POWER as u128 / pool_usd
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_liquidity_fee() {
+        let model = FeeModel {
+            base_bps: 10,
+            tax_bps: 50,
+            stable_tax_bps: 20,
+        };
+        let custody = |value_usd: u128, is_stable| CustodyWeight {
+            is_stable,
+            target_ratio_bps: 5_000,
+            value_usd: value_usd * 1_000_000,
+        };
+        let pool_usd = 1_000_000_000;
+        let amount_usd = 100_000_000;
+        // underweight, deposits move it halfway to target and earn the full rebate
+        let underweight = model.liquidity_fee(&custody(400, false), pool_usd, amount_usd, true);
+        assert_eq!(underweight.current_weight_bps, 4_000);
+        assert_eq!(underweight.next_weight_bps, 4_545);
+        assert_eq!(underweight.fee_bps, 0);
+        // overweight, diffs of 1000 and 1363 bps average 1181 bps, a tax of 50 * 1181 / 5000
+        let overweight = model.liquidity_fee(&custody(600, false), pool_usd, amount_usd, true);
+        assert_eq!(overweight.next_weight_bps, 6_363);
+        assert_eq!(overweight.fee_bps, 21);
+        assert_eq!(overweight.fee_usd, 210_000);
+        // stables are taxed at the stable rate
+        assert_eq!(
+            model.liquidity_fee(&custody(600, true), pool_usd, amount_usd, true).fee_bps,
+            14
+        );
+        // withdrawing from the overweight custody moves it towards target
+        let withdrawal = model.liquidity_fee(&custody(600, false), pool_usd, amount_usd, false);
+        assert_eq!(withdrawal.next_weight_bps, 5_555);
+        assert_eq!(withdrawal.fee_bps, 0);
+        // a small rebate only discounts the base fee
+        let near_target = model.liquidity_fee(&custody(490, false), pool_usd, 10_000_000, true);
+        assert_eq!(near_target.fee_bps, 9);
+    }
+}
@@ -5,9 +5,15 @@ use solana_sdk::{
     instruction::{AccountMeta, Instruction},
     program_pack::Pack,
     pubkey::Pubkey,
+    system_instruction,
 };
 
 pub const LP_TOKEN_MINT: Pubkey = solana_sdk::pubkey!("27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4");
+pub const BPS_POWER: u64 = 10_000;
+/// number of decimals used by the program for usd amounts and prices
+pub const USD_DECIMALS: u8 = 6;
+/// lamports kept back from sol deposits to pay for fees and rent
+pub const SOL_FEE_RESERVE: u64 = 50_000_000;
 
 #[derive(Debug, Clone)]
 pub struct JLPCacheAccountKeys {
@@ -31,7 +37,20 @@ pub struct JLPCustodyAccount {
 pub struct JLPCacheAccounts {
     pub token_mint: spl_token::state::Mint,
     pub pool: crate::Pool,
-    pub usdc_token_account: spl_token::state::Account,
+    /// the owner's token account for the deposit mint, None if it does not exist
+    pub funding_token_account: Option<spl_token::state::Account>,
+    /// lamports held by the owner, used when depositing native sol
+    pub owner_lamports: u64,
+    /// the owner's jlp balance, zero if the lp token account does not exist
+    pub lp_balance: u64,
+}
+
+/// the pool's aum and the jlp supply it backs, enough to value jlp without an owner
+#[derive(Debug, Clone, Copy)]
+pub struct PoolNav {
+    /// scaled by USD_DECIMALS
+    pub aum_usd: u128,
+    pub lp_supply: u64,
 }
 
 impl JLPCacheAccountKeys {
@@ -103,9 +122,19 @@ impl JLPCacheAccountKeys {
             .0,
         })
     }
-    pub async fn load_accounts(&self, rpc: &RpcClient, usdc_ata: Pubkey) -> Result<JLPCacheAccounts> {
+    /// loads the pool, lp mint and the owner's balances for `deposit_mint`
+    pub async fn load_accounts(
+        &self,
+        rpc: &RpcClient,
+        owner: Pubkey,
+        deposit_mint: Pubkey,
+    ) -> Result<JLPCacheAccounts> {
+        let funding_ata =
+            spl_associated_token_account::get_associated_token_address(&owner, &deposit_mint);
+        let lp_ata =
+            spl_associated_token_account::get_associated_token_address(&owner, &LP_TOKEN_MINT);
         let mut accounts = rpc
-            .get_multiple_accounts(&[self.pool, LP_TOKEN_MINT, usdc_ata])
+            .get_multiple_accounts(&[self.pool, LP_TOKEN_MINT, funding_ata, owner, lp_ata])
             .await?;
         let pool_acct = match std::mem::take(&mut accounts[0]) {
             Some(pool_account) => crate::Pool::deserialize(&mut &pool_account.data[8..])?,
@@ -115,24 +144,68 @@ impl JLPCacheAccountKeys {
             Some(lp_account) => spl_token::state::Mint::unpack(&lp_account.data[..])?,
             None => return Err(anyhow!("failed to get mint account")),
         };
-        let usdc_ata = match std::mem::take(&mut accounts[2]) {
-            Some(usdc_ata) => {
-                spl_token::state::Account::unpack(&usdc_ata.data[..])?
-            }
-            None => return Err(anyhow!("failed to get usdc account"))
+        let funding_token_account = match std::mem::take(&mut accounts[2]) {
+            Some(funding_account) => Some(spl_token::state::Account::unpack(
+                &funding_account.data[..],
+            )?),
+            // wrapped sol accounts are created on demand
+            None if deposit_mint.eq(&spl_token::native_mint::id()) => None,
+            None => return Err(anyhow!("failed to get funding token account")),
+        };
+        let owner_lamports = std::mem::take(&mut accounts[3])
+            .map(|owner_account| owner_account.lamports)
+            .unwrap_or_default();
+        let lp_balance = match std::mem::take(&mut accounts[4]) {
+            Some(lp_account) => spl_token::state::Account::unpack(&lp_account.data[..])?.amount,
+            None => 0,
         };
         Ok(JLPCacheAccounts {
             token_mint: lp_mint,
             pool: pool_acct,
-            usdc_token_account: usdc_ata,
+            funding_token_account,
+            owner_lamports,
+            lp_balance,
+        })
+    }
+    /// loads only the pool and the jlp mint
+    pub async fn load_pool_nav(&self, rpc: &RpcClient) -> Result<PoolNav> {
+        let mut accounts = rpc.get_multiple_accounts(&[self.pool, LP_TOKEN_MINT]).await?;
+        let pool = match std::mem::take(&mut accounts[0]) {
+            Some(pool_account) => crate::Pool::deserialize(&mut &pool_account.data[8..])?,
+            None => return Err(anyhow!("failed to get pool account")),
+        };
+        let lp_mint = match std::mem::take(&mut accounts[1]) {
+            Some(lp_account) => spl_token::state::Mint::unpack(&lp_account.data[..])?,
+            None => return Err(anyhow!("failed to get mint account")),
+        };
+        Ok(PoolNav {
+            aum_usd: pool.aum_usd,
+            lp_supply: lp_mint.supply,
         })
     }
+    /// loads the custody account of every custody token, in the same order as custody_accounts
+    pub async fn load_custodies(&self, rpc: &RpcClient) -> Result<Vec<crate::Custody>> {
+        let keys = self
+            .custody_accounts
+            .iter()
+            .map(|custody| custody.account)
+            .collect::<Vec<_>>();
+        let mut custodies = Vec::with_capacity(keys.len());
+        for (key, account) in keys.iter().zip(rpc.get_multiple_accounts(&keys).await?) {
+            let account = account.with_context(|| format!("failed to get custody account {key}"))?;
+            custodies.push(crate::Custody::deserialize(&mut &account.data[8..])?);
+        }
+        Ok(custodies)
+    }
+    /// when the deposit is funded by a swap earlier in the same transaction, `token_amount_pre_swap`
+    /// is the amount of the original input token swapped into the custody token
     pub fn generate_liquidity_add_ix(
         &self,
         deposit_mint: Pubkey,
         owner: Pubkey,
         deposit_amount: u64,
         min_out: u64,
+        token_amount_pre_swap: Option<u64>,
     ) -> Result<Instruction> {
         let custody_info = self
             .custody_account_for_mint(deposit_mint)
@@ -141,7 +214,7 @@ impl JLPCacheAccountKeys {
             _params: crate::AddLiquidityParams {
                 token_amount_in: deposit_amount,
                 min_lp_amount_out: min_out,
-                token_amount_pre_swap: None,
+                token_amount_pre_swap,
             },
         };
         let mut ix_accounts = crate::accounts::AddLiquidity {
@@ -166,17 +239,84 @@ impl JLPCacheAccountKeys {
             program: crate::id(),
         }
         .to_account_metas(None);
+        ix_accounts.extend(self.custody_metas(Some(deposit_mint)));
 
-        for custody in &self.custody_accounts {
-            if custody.mint.eq(&deposit_mint) {
-                ix_accounts.push(AccountMeta::new(custody.account, false));
-            } else {
-                ix_accounts.push(AccountMeta::new_readonly(custody.account, false));
-            }
+        Ok(Instruction {
+            program_id: crate::id(),
+            accounts: ix_accounts,
+            data: ix_data.data(),
+        })
+    }
+    pub fn generate_liquidity_remove_ix(
+        &self,
+        receive_mint: Pubkey,
+        owner: Pubkey,
+        lp_amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<Instruction> {
+        let custody_info = self
+            .custody_account_for_mint(receive_mint)
+            .with_context(|| "no custody account for mint")?;
+        let ix_data = crate::instruction::RemoveLiquidity {
+            _params: crate::RemoveLiquidityParams {
+                lp_amount_in,
+                min_amount_out,
+            },
+        };
+        let mut ix_accounts = crate::accounts::RemoveLiquidity {
+            owner,
+            receiving_account: spl_associated_token_account::get_associated_token_address(
+                &owner,
+                &receive_mint,
+            ),
+            lp_token_account: spl_associated_token_account::get_associated_token_address(
+                &owner,
+                &LP_TOKEN_MINT,
+            ),
+            transfer_authority: self.transfer_authority,
+            perpetuals: self.perp,
+            pool: self.pool,
+            custody: custody_info.account,
+            custody_oracle_account: custody_info.oracle_account,
+            custody_token_account: custody_info.token_account,
+            lp_token_mint: LP_TOKEN_MINT,
+            token_program: spl_token::id(),
+            event_authority: self.event_authority,
+            program: crate::id(),
         }
-        for custody in &self.custody_accounts {
-            ix_accounts.push(AccountMeta::new_readonly(custody.oracle_account, false));
+        .to_account_metas(None);
+        ix_accounts.extend(self.custody_metas(Some(receive_mint)));
+
+        Ok(Instruction {
+            program_id: crate::id(),
+            accounts: ix_accounts,
+            data: ix_data.data(),
+        })
+    }
+    /// generates the getAddLiquidityAmountAndFee view instruction, which returns the
+    /// amount of lp tokens minted and the fee charged for depositing `deposit_amount`
+    pub fn generate_add_liquidity_amount_and_fee_ix(
+        &self,
+        deposit_mint: Pubkey,
+        deposit_amount: u64,
+    ) -> Result<Instruction> {
+        let custody_info = self
+            .custody_account_for_mint(deposit_mint)
+            .with_context(|| "no custody account for mint")?;
+        let ix_data = crate::instruction::GetAddLiquidityAmountAndFee {
+            _params: crate::GetAddLiquidityAmountAndFeeParams {
+                token_amount_in: deposit_amount,
+            },
+        };
+        let mut ix_accounts = crate::accounts::GetAddLiquidityAmountAndFee {
+            perpetuals: self.perp,
+            pool: self.pool,
+            custody: custody_info.account,
+            custody_oracle_account: custody_info.oracle_account,
+            lp_token_mint: LP_TOKEN_MINT,
         }
+        .to_account_metas(None);
+        ix_accounts.extend(self.custody_metas(None));
 
         Ok(Instruction {
             program_id: crate::id(),
@@ -184,12 +324,262 @@ impl JLPCacheAccountKeys {
             data: ix_data.data(),
         })
     }
+    /// quotes the amount of lp tokens the program will mint, and the fee it will charge, for a deposit
+    pub async fn quote_add_liquidity(
+        &self,
+        rpc: &RpcClient,
+        payer: Pubkey,
+        deposit_mint: Pubkey,
+        deposit_amount: u64,
+    ) -> Result<crate::AmountAndFee> {
+        crate::view::simulate_view(
+            rpc,
+            payer,
+            self.generate_add_liquidity_amount_and_fee_ix(deposit_mint, deposit_amount)?,
+        )
+        .await
+    }
+    /// generates the getRemoveLiquidityAmountAndFee view instruction, which returns the amount
+    /// of custody tokens received and the fee charged for redeeming `lp_amount_in`
+    pub fn generate_remove_liquidity_amount_and_fee_ix(
+        &self,
+        receive_mint: Pubkey,
+        lp_amount_in: u64,
+    ) -> Result<Instruction> {
+        let custody_info = self
+            .custody_account_for_mint(receive_mint)
+            .with_context(|| "no custody account for mint")?;
+        let ix_data = crate::instruction::GetRemoveLiquidityAmountAndFee {
+            _params: crate::GetRemoveLiquidityAmountAndFeeParams { lp_amount_in },
+        };
+        let mut ix_accounts = crate::accounts::GetRemoveLiquidityAmountAndFee {
+            perpetuals: self.perp,
+            pool: self.pool,
+            custody: custody_info.account,
+            custody_oracle_account: custody_info.oracle_account,
+            lp_token_mint: LP_TOKEN_MINT,
+        }
+        .to_account_metas(None);
+        ix_accounts.extend(self.custody_metas(None));
+
+        Ok(Instruction {
+            program_id: crate::id(),
+            accounts: ix_accounts,
+            data: ix_data.data(),
+        })
+    }
+    /// quotes the amount of custody tokens the program will return, and the fee it will charge, for a withdrawal
+    pub async fn quote_remove_liquidity(
+        &self,
+        rpc: &RpcClient,
+        payer: Pubkey,
+        receive_mint: Pubkey,
+        lp_amount_in: u64,
+    ) -> Result<crate::AmountAndFee> {
+        crate::view::simulate_view(
+            rpc,
+            payer,
+            self.generate_remove_liquidity_amount_and_fee_ix(receive_mint, lp_amount_in)?,
+        )
+        .await
+    }
+    /// generates the getOraclePrice view instruction for the custody of `mint`
+    pub fn generate_oracle_price_ix(&self, mint: Pubkey) -> Result<Instruction> {
+        let custody_info = self
+            .custody_account_for_mint(mint)
+            .with_context(|| "no custody account for mint")?;
+        let ix_data = crate::instruction::GetOraclePrice;
+        let ix_accounts = crate::accounts::GetOraclePrice {
+            perpetuals: self.perp,
+            pool: self.pool,
+            custody: custody_info.account,
+            custody_oracle_account: custody_info.oracle_account,
+        }
+        .to_account_metas(None);
+
+        Ok(Instruction {
+            program_id: crate::id(),
+            accounts: ix_accounts,
+            data: ix_data.data(),
+        })
+    }
+    /// generates the getAssetsUnderManagement view instruction, which values the pool from
+    /// its custodies and their oracles
+    pub fn generate_assets_under_management_ix(
+        &self,
+        mode: Option<crate::PriceCalcMode>,
+    ) -> Instruction {
+        let ix_data = crate::instruction::GetAssetsUnderManagement {
+            _params: crate::GetAssetsUnderManagementParams { mode },
+        };
+        let mut ix_accounts = crate::accounts::GetAssetsUnderManagement {
+            perpetuals: self.perp,
+            pool: self.pool,
+        }
+        .to_account_metas(None);
+        ix_accounts.extend(self.custody_metas(None));
+
+        Instruction {
+            program_id: crate::id(),
+            accounts: ix_accounts,
+            data: ix_data.data(),
+        }
+    }
+    /// returns the pool's aum as valued by the program, scaled by USD_DECIMALS
+    pub async fn assets_under_management(
+        &self,
+        rpc: &RpcClient,
+        payer: Pubkey,
+        mode: Option<crate::PriceCalcMode>,
+    ) -> Result<u128> {
+        crate::view::simulate_view(rpc, payer, self.generate_assets_under_management_ix(mode)).await
+    }
+    /// reads the price of the custody token for `mint` from its custody oracle account, as valued by the program.
+    /// prices are usd amounts scaled by USD_DECIMALS
+    pub async fn oracle_price(
+        &self,
+        rpc: &RpcClient,
+        payer: Pubkey,
+        mint: Pubkey,
+    ) -> Result<crate::OraclePriceInfo> {
+        crate::view::simulate_view(rpc, payer, self.generate_oracle_price_ix(mint)?).await
+    }
+    /// returns the custody and oracle accounts passed as remaining accounts to instructions that
+    /// value the pool, custodies first followed by their oracles. only the custody for `writable_mint` is writable
+    pub fn custody_metas(&self, writable_mint: Option<Pubkey>) -> Vec<AccountMeta> {
+        let mut metas = Vec::with_capacity(self.custody_accounts.len() * 2);
+        for custody in &self.custody_accounts {
+            if Some(custody.mint) == writable_mint {
+                metas.push(AccountMeta::new(custody.account, false));
+            } else {
+                metas.push(AccountMeta::new_readonly(custody.account, false));
+            }
+        }
+        for custody in &self.custody_accounts {
+            metas.push(AccountMeta::new_readonly(custody.oracle_account, false));
+        }
+        metas
+    }
+}
+
+/// generates the instructions needed to wrap `lamports` into the owner's wrapped sol account,
+/// creating the account if it does not exist
+pub fn wrap_sol_ixs(owner: Pubkey, lamports: u64) -> Vec<Instruction> {
+    let wsol_ata = spl_associated_token_account::get_associated_token_address(
+        &owner,
+        &spl_token::native_mint::id(),
+    );
+    vec![
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &owner,
+            &owner,
+            &spl_token::native_mint::id(),
+            &spl_token::id(),
+        ),
+        system_instruction::transfer(&owner, &wsol_ata, lamports),
+        spl_token::instruction::sync_native(&spl_token::id(), &wsol_ata)
+            .expect("sync native should always build"),
+    ]
+}
+
+/// generates the instruction closing the owner's wrapped sol account, unwrapping any remaining balance
+pub fn unwrap_sol_ix(owner: Pubkey) -> Instruction {
+    let wsol_ata = spl_associated_token_account::get_associated_token_address(
+        &owner,
+        &spl_token::native_mint::id(),
+    );
+    spl_token::instruction::close_account(&spl_token::id(), &wsol_ata, &owner, &owner, &[])
+        .expect("close account should always build")
+}
+
+/// converts a usd amount (scaled by USD_DECIMALS) into an amount of tokens with `decimals` at `price`
+pub fn usd_to_token_amount(usd_amount: u128, price: u64, decimals: u8) -> u64 {
+    if price == 0 {
+        return 0;
+    }
+    let amount = usd_amount * 10_u128.pow(decimals as u32) / price as u128;
+    amount.min(u64::MAX as u128) as u64
+}
+
+/// converts an amount of tokens with `decimals` into a usd amount (scaled by USD_DECIMALS) at `price`
+pub fn token_to_usd_amount(token_amount: u64, price: u64, decimals: u8) -> u128 {
+    token_amount as u128 * price as u128 / 10_u128.pow(decimals as u32)
+}
+
+/// converts a usd amount scaled by USD_DECIMALS into dollars
+pub fn usd_to_ui(usd_amount: u128) -> f64 {
+    usd_amount as f64 / 10_u64.pow(USD_DECIMALS as u32) as f64
+}
+
+/// returns the minimum amount to accept for `amount` given a slippage tolerance in bps
+pub fn apply_slippage(amount: u64, slippage_bps: u64) -> u64 {
+    let slippage_bps = slippage_bps.min(BPS_POWER);
+    ((amount as u128 * (BPS_POWER - slippage_bps) as u128) / BPS_POWER as u128) as u64
 }
 
 impl JLPCacheAccounts {
+    /// returns the amount of `deposit_mint` the owner can deposit. for native sol this includes
+    /// unwrapped lamports, less a reserve kept back for fees and rent
+    pub fn deposit_balance(&self, deposit_mint: Pubkey) -> u64 {
+        let token_balance = self
+            .funding_token_account
+            .map(|tkn_acct| tkn_acct.amount)
+            .unwrap_or_default();
+        if deposit_mint.eq(&spl_token::native_mint::id()) {
+            token_balance + self.owner_lamports.saturating_sub(SOL_FEE_RESERVE)
+        } else {
+            token_balance
+        }
+    }
+    /// returns the usd (scaled by USD_DECIMALS) that can be deposited before the pool hits its aum cap
+    pub fn room_for_deposit_usd(&self) -> u128 {
+        self.pool
+            .limit
+            .max_aum_usd
+            .saturating_sub(self.pool.aum_usd)
+    }
+    /// returns the jlp the owner can still receive before hitting the pool's per-user
+    /// limit, None if the pool has no per-user limit
+    pub fn room_for_lp_tokens(&self) -> Option<u64> {
+        // a zero limit disables the per-user cap
+        if self.pool.limit.max_individual_lp_token == 0 {
+            return None;
+        }
+        let room = self
+            .pool
+            .limit
+            .max_individual_lp_token
+            .saturating_sub(self.lp_balance as u128);
+        Some(room.min(u64::MAX as u128) as u64)
+    }
+    /// returns the usd (scaled by USD_DECIMALS) value of `lp_amount` at the pool's current aum
+    pub fn lp_to_usd_amount(&self, lp_amount: u64) -> u128 {
+        PoolNav {
+            aum_usd: self.pool.aum_usd,
+            lp_supply: self.token_mint.supply,
+        }
+        .lp_to_usd_amount(lp_amount)
+    }
+    /// returns the jlp worth `usd_amount` (scaled by USD_DECIMALS) at the pool's current aum
+    pub fn usd_to_lp_amount(&self, usd_amount: u128) -> u64 {
+        if self.pool.aum_usd == 0 {
+            return 0;
+        }
+        (usd_amount * self.token_mint.supply as u128 / self.pool.aum_usd).min(u64::MAX as u128) as u64
+    }
     pub fn calculate_jlp_price(&self) -> f64 {
         let supply =
             spl_token::amount_to_ui_amount(self.token_mint.supply, self.token_mint.decimals);
         (self.pool.aum_usd as f64 / supply) / 10_usize.pow(self.token_mint.decimals as u32) as f64
     }
 }
+
+impl PoolNav {
+    /// returns the usd (scaled by USD_DECIMALS) value of `lp_amount` at the pool's aum
+    pub fn lp_to_usd_amount(&self, lp_amount: u64) -> u128 {
+        if self.lp_supply == 0 {
+            return 0;
+        }
+        lp_amount as u128 * self.aum_usd / self.lp_supply as u128
+    }
+}
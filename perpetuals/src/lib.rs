@@ -11,4 +11,11 @@ anchor_gen::generate_cpi_interface!(idl_path = "idl.json");
 
 declare_id!("PERPHjGBqRHArX4DySjwM6UJHiR3sWAatqfdBS2qQJu");
 
+pub mod aum;
+pub mod capacity;
+pub mod event_parser;
+pub mod fees;
 pub mod jlp_cacher;
+pub mod oracle;
+pub mod program_error;
+pub mod view;
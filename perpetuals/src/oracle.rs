@@ -0,0 +1,294 @@
+//! decodes the pyth price accounts custodies are priced from, rejecting stale and
+//! uncertain prices the same way the program does before it values the pool
+
+use crate::jlp_cacher::{JLPCacheAccountKeys, BPS_POWER, USD_DECIMALS};
+use crate::program_error::ProgramError;
+use anyhow::{anyhow, Context, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_VERSION: u32 = 2;
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+/// header, aggregate price and 32 publisher components
+pub const PYTH_PRICE_ACCOUNT_SIZE: usize = 3312;
+
+/// status of a pyth aggregate price, only trading prices are current
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceStatus {
+    Unknown,
+    Trading,
+    Halted,
+    Auction,
+    Ignored,
+}
+
+impl From<u32> for PriceStatus {
+    fn from(status: u32) -> Self {
+        match status {
+            1 => Self::Trading,
+            2 => Self::Halted,
+            3 => Self::Auction,
+            4 => Self::Ignored,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// a price of `price * 10^expo`, give or take `conf * 10^expo`, published at `publish_time`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PythPrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+/// the fields of a pyth v2 price account the program reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PythPriceAccount {
+    pub status: PriceStatus,
+    /// the aggregate price while trading, otherwise the last price published while trading
+    pub price: PythPrice,
+    pub ema_price: PythPrice,
+}
+
+impl PythPriceAccount {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < PYTH_PRICE_ACCOUNT_SIZE {
+            return Err(anyhow!("pyth price account too short, {} bytes", data.len()));
+        }
+        if read_u32(data, 0) != PYTH_MAGIC {
+            return Err(anyhow!("not a pyth account"));
+        }
+        if read_u32(data, 4) != PYTH_VERSION {
+            return Err(anyhow!("unsupported pyth account version {}", read_u32(data, 4)));
+        }
+        if read_u32(data, 8) != PYTH_PRICE_ACCOUNT_TYPE {
+            return Err(anyhow!("not a pyth price account"));
+        }
+        let expo = read_u32(data, 20) as i32;
+        let status = PriceStatus::from(read_u32(data, 224));
+        let (price, conf, publish_time) = if status == PriceStatus::Trading {
+            (read_u64(data, 208) as i64, read_u64(data, 216), read_u64(data, 96) as i64)
+        } else {
+            (read_u64(data, 184) as i64, read_u64(data, 192), read_u64(data, 200) as i64)
+        };
+        Ok(Self {
+            status,
+            price: PythPrice {
+                price,
+                conf,
+                expo,
+                publish_time,
+            },
+            // the ema is always reported with the publish time of the price
+            ema_price: PythPrice {
+                price: read_u64(data, 48) as i64,
+                conf: read_u64(data, 72),
+                expo,
+                publish_time,
+            },
+        })
+    }
+}
+
+/// a validated oracle price of `price * 10^exponent` usd
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: u64,
+    pub exponent: i32,
+    pub conf: u64,
+    pub publish_time: i64,
+}
+
+impl OraclePrice {
+    /// validates `price` against the custody's oracle params at unix timestamp `now`, failing
+    /// with StaleOraclePrice when it is older than max_price_age_sec and InvalidOraclePrice
+    /// when it isn't positive or its confidence, in whole bps of the price, exceeds
+    /// max_price_error
+    pub fn new(price: &PythPrice, params: &crate::OracleParams, now: i64) -> Result<Self> {
+        let age = now.saturating_sub(price.publish_time);
+        if age > params.max_price_age_sec as i64 {
+            return Err(anyhow!(
+                "{} price is {age}s old, max {}s",
+                program_error(6003),
+                params.max_price_age_sec
+            ));
+        }
+        // the program truncates the confidence to whole bps before comparing
+        let conf_bps = (price.conf as u128 * BPS_POWER as u128).checked_div(price.price.max(0) as u128);
+        if price.price <= 0 || conf_bps.unwrap_or(u128::MAX) > params.max_price_error as u128 {
+            return Err(anyhow!(
+                "{} price {} conf {}, max error {} bps",
+                program_error(6004),
+                price.price,
+                price.conf,
+                params.max_price_error
+            ));
+        }
+        Ok(Self {
+            price: price.price as u64,
+            exponent: price.expo,
+            conf: price.conf,
+            publish_time: price.publish_time,
+        })
+    }
+    /// rescales the price to `exponent`, truncating when digits are dropped
+    pub fn scale_to_exponent(&self, exponent: i32) -> Result<Self> {
+        let scale = |amount: u64| -> Option<u64> {
+            let delta = exponent - self.exponent;
+            if delta >= 0 {
+                Some(amount / 10_u64.checked_pow(delta as u32)?)
+            } else {
+                amount.checked_mul(10_u64.checked_pow(delta.unsigned_abs())?)
+            }
+        };
+        Ok(Self {
+            price: scale(self.price).with_context(|| "overflow scaling oracle price")?,
+            exponent,
+            conf: scale(self.conf).with_context(|| "overflow scaling oracle confidence")?,
+            publish_time: self.publish_time,
+        })
+    }
+    /// the price as a usd amount scaled by USD_DECIMALS, as used throughout the program
+    pub fn usd(&self) -> Result<u64> {
+        Ok(self.scale_to_exponent(-(USD_DECIMALS as i32))?.price)
+    }
+    pub fn as_f64(&self) -> f64 {
+        self.price as f64 * 10_f64.powi(self.exponent)
+    }
+}
+
+/// the validated aggregate and ema prices of a custody's oracle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustodyPrice {
+    pub price: OraclePrice,
+    pub ema_price: OraclePrice,
+}
+
+impl JLPCacheAccountKeys {
+    /// fetches and validates the pyth prices of every custody, in the same order as custodies
+    pub async fn oracle_prices(
+        &self,
+        rpc: &RpcClient,
+        custodies: &[crate::Custody],
+    ) -> Result<Vec<CustodyPrice>> {
+        let keys = custodies
+            .iter()
+            .map(|custody| custody.oracle.oracle_account)
+            .collect::<Vec<_>>();
+        let accounts = rpc.get_multiple_accounts(&keys).await?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        custodies
+            .iter()
+            .zip(keys.iter().zip(accounts))
+            .map(|(custody, (key, account))| {
+                if !matches!(custody.oracle.oracle_type, crate::OracleType::Pyth) {
+                    return Err(anyhow!("{} oracle {key}", program_error(6001)));
+                }
+                let account = account.with_context(|| format!("failed to get oracle account {key}"))?;
+                let pyth = PythPriceAccount::decode(&account.data)
+                    .with_context(|| format!("{} {key}", program_error(6002)))?;
+                let validate = |price| {
+                    OraclePrice::new(price, &custody.oracle, now)
+                        .with_context(|| format!("oracle {key} of {}", custody.mint))
+                };
+                Ok(CustodyPrice {
+                    price: validate(&pyth.price)?,
+                    ema_price: validate(&pyth.ema_price)?,
+                })
+            })
+            .collect()
+    }
+}
+
+fn program_error(code: u32) -> ProgramError {
+    ProgramError::from_code(code).unwrap()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn price_account(status: u32) -> Vec<u8> {
+        let mut data = vec![0_u8; PYTH_PRICE_ACCOUNT_SIZE];
+        let mut write = |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        write(0, &PYTH_MAGIC.to_le_bytes());
+        write(4, &PYTH_VERSION.to_le_bytes());
+        write(8, &PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+        write(20, &(-8_i32).to_le_bytes());
+        write(48, &14_900_000_000_i64.to_le_bytes());
+        write(72, &9_000_000_u64.to_le_bytes());
+        write(96, &1_700_000_100_i64.to_le_bytes());
+        write(184, &14_800_000_000_i64.to_le_bytes());
+        write(192, &8_000_000_u64.to_le_bytes());
+        write(200, &1_700_000_000_i64.to_le_bytes());
+        write(208, &15_000_000_000_i64.to_le_bytes());
+        write(216, &10_000_000_u64.to_le_bytes());
+        write(224, &status.to_le_bytes());
+        data
+    }
+    fn params(max_price_error: u64, max_price_age_sec: u32) -> crate::OracleParams {
+        crate::OracleParams {
+            oracle_account: Default::default(),
+            oracle_type: crate::OracleType::Pyth,
+            max_price_error,
+            max_price_age_sec,
+        }
+    }
+    #[test]
+    fn test_decode() {
+        let trading = PythPriceAccount::decode(&price_account(1)).unwrap();
+        assert_eq!(trading.status, PriceStatus::Trading);
+        assert_eq!(
+            trading.price,
+            PythPrice {
+                price: 15_000_000_000,
+                conf: 10_000_000,
+                expo: -8,
+                publish_time: 1_700_000_100,
+            }
+        );
+        assert_eq!(trading.ema_price.price, 14_900_000_000);
+        assert_eq!(trading.ema_price.conf, 9_000_000);
+        // halted feeds fall back to the last price published while trading
+        let halted = PythPriceAccount::decode(&price_account(2)).unwrap();
+        assert_eq!(halted.status, PriceStatus::Halted);
+        assert_eq!(halted.price.price, 14_800_000_000);
+        assert_eq!(halted.price.publish_time, 1_700_000_000);
+        assert_eq!(halted.ema_price.publish_time, 1_700_000_000);
+
+        let mut data = price_account(1);
+        data[0] = 0;
+        assert!(PythPriceAccount::decode(&data).is_err());
+        assert!(PythPriceAccount::decode(&price_account(1)[..240]).is_err());
+    }
+    #[test]
+    fn test_oracle_price() {
+        let pyth = PythPriceAccount::decode(&price_account(1)).unwrap();
+        // conf is 6.67 bps of the price, truncated to 6
+        let price = OraclePrice::new(&pyth.price, &params(6, 60), 1_700_000_160).unwrap();
+        assert_eq!(price.usd().unwrap(), 150_000_000);
+        assert_eq!(price.as_f64(), 150.0);
+        assert_eq!(price.scale_to_exponent(-10).unwrap().price, 1_500_000_000_000);
+        let err = OraclePrice::new(&pyth.price, &params(5, 60), 1_700_000_160).unwrap_err();
+        assert!(err.to_string().contains("InvalidOraclePrice"));
+        let err = OraclePrice::new(&pyth.price, &params(7, 60), 1_700_000_161).unwrap_err();
+        assert!(err.to_string().contains("StaleOraclePrice"));
+        let negative = PythPrice {
+            price: -1,
+            ..pyth.price
+        };
+        assert!(OraclePrice::new(&negative, &params(10_000, 60), 1_700_000_100).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! decoding of the custom error codes returned by the perpetuals program
+
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+/// custom errors defined by the perpetuals idl as (code, name, message)
+const PROGRAM_ERRORS: &[(u32, &str, &str)] = &[
+    (6000, "MathOverflow", "Overflow in arithmetic operation"),
+    (6001, "UnsupportedOracle", "Unsupported price oracle"),
+    (6002, "InvalidOracleAccount", "Invalid oracle account"),
+    (6003, "StaleOraclePrice", "Stale oracle price"),
+    (6004, "InvalidOraclePrice", "Invalid oracle price"),
+    (6005, "InvalidEnvironment", "Instruction is not allowed in production"),
+    (6006, "InvalidCollateralAccount", "Invalid collateral account"),
+    (6007, "InvalidCollateralAmount", "Invalid collateral amount"),
+    (6008, "CollateralSlippage", "Collateral slippage"),
+    (6009, "InvalidPositionState", "Invalid position state"),
+    (6010, "InvalidPerpetualsConfig", "Invalid perpetuals config"),
+    (6011, "InvalidPoolConfig", "Invalid pool config"),
+    (6012, "InvalidInstruction", "Invalid instruction"),
+    (6013, "InvalidCustodyConfig", "Invalid custody config"),
+    (6014, "InvalidCustodyBalance", "Invalid custody balance"),
+    (6015, "InvalidArgument", "Invalid argument"),
+    (6016, "InvalidPositionRequest", "Invalid position request"),
+    (6017, "InvalidPositionRequestInputAta", "Invalid position request input ata"),
+    (6018, "InvalidMint", "Invalid mint"),
+    (6019, "InsufficientTokenAmount", "Insufficient token amount"),
+    (6020, "InsufficientAmountReturned", "Insufficient token amount returned"),
+    (6021, "MaxPriceSlippage", "Price slippage limit exceeded"),
+    (6022, "MaxLeverage", "Position leverage limit exceeded"),
+    (6023, "CustodyAmountLimit", "Custody amount limit exceeded"),
+    (6024, "PoolAmountLimit", "Pool amount limit exceeded"),
+    (6025, "PersonalPoolAmountLimit", "Personal pool amount limit exceeded"),
+    (6026, "UnsupportedToken", "Token is not supported"),
+    (6027, "InstructionNotAllowed", "Instruction is not allowed at this time"),
+    (6028, "JupiterProgramMismatch", "Jupiter Program ID mismatch"),
+    (6029, "ProgramMismatch", "Program ID mismatch"),
+    (6030, "AddressMismatch", "Address mismatch"),
+    (6031, "KeeperATAMissing", "Missing keeper ATA"),
+    (6032, "SwapAmountMismatch", "Swap amount mismatch"),
+    (6033, "CPINotAllowed", "CPI not allowed"),
+    (6034, "InvalidKeeper", "Invalid Keeper"),
+    (6035, "ExceedExecutionPeriod", "Exceed execution period"),
+    (6036, "InvalidRequestType", "Invalid Request Type"),
+    (6037, "InvalidTriggerPrice", "Invalid Trigger Price"),
+    (6038, "TriggerPriceSlippage", "Trigger Price Slippage"),
+    (6039, "MissingTriggerPrice", "Missing Trigger Price"),
+    (6040, "MissingPriceSlippage", "Missing Price Slippage"),
+    (6041, "InvalidPriceCalcMode", "Invalid Price Calc Mode"),
+    (6042, "RequestUpdatedTooRecent", "Request Updated Too Recent"),
+];
+
+/// a custom error returned by the perpetuals program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramError {
+    pub code: u32,
+    pub name: &'static str,
+    pub msg: &'static str,
+}
+
+impl ProgramError {
+    pub fn from_code(code: u32) -> Option<ProgramError> {
+        PROGRAM_ERRORS
+            .iter()
+            .find(|(err_code, _, _)| *err_code == code)
+            .map(|(code, name, msg)| ProgramError {
+                code: *code,
+                name,
+                msg,
+            })
+    }
+    /// returns the program error wrapped by a failed transaction, if the failure
+    /// was caused by a custom error from the perpetuals program
+    pub fn from_transaction_error(err: &TransactionError) -> Option<ProgramError> {
+        match err {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                Self::from_code(*code)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.name, self.code, self.msg)
+    }
+}
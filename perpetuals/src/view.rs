@@ -0,0 +1,54 @@
+//! helpers for invoking the perpetuals program's view instructions (getAddLiquidityAmountAndFee, etc..)
+//! through transaction simulation and decoding their return data
+
+use anchor_lang::AnchorDeserialize;
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as b64, Engine};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSimulateTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey,
+    transaction::Transaction,
+};
+
+use crate::program_error::ProgramError;
+
+/// simulates the given view instruction and deserializes the value it returns.
+///
+/// the payer is only used as the fee payer of the simulated transaction, and must be an existing account
+pub async fn simulate_view<T: AnchorDeserialize>(
+    rpc: &RpcClient,
+    payer: Pubkey,
+    ix: Instruction,
+) -> Result<T> {
+    let tx = Transaction::new_with_payer(&[ix], Some(&payer));
+    let sim = rpc
+        .simulate_transaction_with_config(
+            &tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                ..Default::default()
+            },
+        )
+        .await
+        .with_context(|| "failed to simulate view instruction")?
+        .value;
+    if let Some(err) = sim.err {
+        return match ProgramError::from_transaction_error(&err) {
+            Some(program_err) => Err(anyhow!("view instruction failed {program_err}")),
+            None => Err(anyhow!("view instruction failed {err:#?}")),
+        };
+    }
+    let return_data = sim
+        .return_data
+        .with_context(|| "view instruction did not return data")?;
+    if return_data.program_id != crate::id().to_string() {
+        return Err(anyhow!(
+            "unexpected return data from program {}",
+            return_data.program_id
+        ));
+    }
+    let data = b64.decode(&return_data.data.0)?;
+    Ok(T::deserialize(&mut &data[..])?)
+}
@@ -0,0 +1,193 @@
+//! compute unit limits sized from simulating the transaction, cached by instruction shape
+//! so repeated transactions of the same shape only pay for one simulation
+
+use anyhow::{anyhow, Context, Result};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_config::RpcSimulateTransactionConfig,
+};
+use solana_sdk::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction, hash::Hash,
+    instruction::Instruction, message::VersionedMessage, pubkey::Pubkey, signature::Signature,
+    transaction::VersionedTransaction,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::sync::{Arc, Mutex};
+
+/// the most compute units a transaction can request
+pub const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+/// consumed by the compute unit limit and price instructions, which aren't simulated
+const COMPUTE_BUDGET_UNITS: u64 = 300;
+const BPS_POWER: u64 = 10_000;
+
+/// sizes compute unit limits as the units consumed in simulation plus a margin. clones
+/// share the same cache
+#[derive(Clone)]
+pub struct ComputeUnitEstimator {
+    rpc: Arc<RpcClient>,
+    margin_bps: u64,
+    cache: Arc<Mutex<HashMap<u64, u32>>>,
+}
+
+impl ComputeUnitEstimator {
+    pub fn new(rpc: Arc<RpcClient>) -> Self {
+        Self {
+            rpc,
+            margin_bps: 2_000,
+            cache: Default::default(),
+        }
+    }
+    /// margin in bps added on top of the simulated units consumed
+    pub fn with_margin_bps(mut self, margin_bps: u64) -> Self {
+        self.margin_bps = margin_bps;
+        self
+    }
+    /// returns the compute unit limit for `instructions`, which must not include compute budget
+    /// instructions. simulates the first transaction of each shape, falling back to
+    /// MAX_COMPUTE_UNITS when the simulation fails
+    pub async fn limit(
+        &self,
+        payer: Pubkey,
+        instructions: &[Instruction],
+        luts: &[AddressLookupTableAccount],
+    ) -> u32 {
+        match self.estimate(payer, instructions, luts).await {
+            Ok(limit) => limit,
+            Err(err) => {
+                log::warn!("failed to size compute unit limit {err:#}, using {MAX_COMPUTE_UNITS}");
+                MAX_COMPUTE_UNITS
+            }
+        }
+    }
+    /// like `limit`, but falls back to `fallback` when the simulation fails and caches it for
+    /// the shape, for transactions that can't be simulated until they are sent
+    pub async fn limit_or(
+        &self,
+        payer: Pubkey,
+        instructions: &[Instruction],
+        luts: &[AddressLookupTableAccount],
+        fallback: u32,
+    ) -> u32 {
+        match self.estimate(payer, instructions, luts).await {
+            Ok(limit) => limit,
+            Err(err) => {
+                log::warn!("failed to size compute unit limit {err:#}, using {fallback}");
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(instruction_shape(instructions), fallback);
+                fallback
+            }
+        }
+    }
+    async fn estimate(
+        &self,
+        payer: Pubkey,
+        instructions: &[Instruction],
+        luts: &[AddressLookupTableAccount],
+    ) -> Result<u32> {
+        let shape = instruction_shape(instructions);
+        if let Some(limit) = self.cache.lock().unwrap().get(&shape) {
+            return Ok(*limit);
+        }
+        let units_consumed = self.simulate(payer, instructions, luts).await?;
+        let limit = self.with_margin(units_consumed);
+        log::debug!("simulation consumed {units_consumed} compute units, limiting to {limit}");
+        self.cache.lock().unwrap().insert(shape, limit);
+        Ok(limit)
+    }
+    async fn simulate(
+        &self,
+        payer: Pubkey,
+        instructions: &[Instruction],
+        luts: &[AddressLookupTableAccount],
+    ) -> Result<u64> {
+        let ixs = std::iter::once(ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS))
+            .chain(instructions.iter().cloned())
+            .collect::<Vec<_>>();
+        // the blockhash is replaced and signatures aren't verified by the simulation
+        let message = solana_sdk::message::v0::Message::try_compile(&payer, &ixs, luts, Hash::default())?;
+        let tx = VersionedTransaction {
+            signatures: vec![Signature::default(); message.header.num_required_signatures as usize],
+            message: VersionedMessage::V0(message),
+        };
+        let sim = self
+            .rpc
+            .simulate_transaction_with_config(
+                &tx,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(CommitmentConfig::processed()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .with_context(|| "failed to simulate transaction")?
+            .value;
+        if let Some(err) = sim.err {
+            return Err(anyhow!("simulation failed {err}"));
+        }
+        sim.units_consumed
+            .ok_or_else(|| anyhow!("simulation did not report units consumed"))
+    }
+    fn with_margin(&self, units_consumed: u64) -> u32 {
+        let limit = (units_consumed + COMPUTE_BUDGET_UNITS) * (BPS_POWER + self.margin_bps) / BPS_POWER;
+        u32::try_from(limit).unwrap_or(MAX_COMPUTE_UNITS).min(MAX_COMPUTE_UNITS)
+    }
+}
+
+/// hashes the programs, accounts and data layout of `instructions`, ignoring the data itself
+/// so the same transaction with different amounts shares a cached limit
+fn instruction_shape(instructions: &[Instruction]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for ix in instructions {
+        hasher.write(ix.program_id.as_ref());
+        for account in &ix.accounts {
+            hasher.write(account.pubkey.as_ref());
+            hasher.write_u8(u8::from(account.is_signer) | u8::from(account.is_writable) << 1);
+        }
+        hasher.write_usize(ix.data.len());
+        // anchor discriminators, and the instruction tag of native programs
+        hasher.write(&ix.data[..ix.data.len().min(8)]);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+    #[test]
+    fn test_instruction_shape() {
+        let (program, account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = |accounts: Vec<AccountMeta>, data: &[u8]| Instruction::new_with_bytes(program, data, accounts);
+        let discriminator = [1, 2, 3, 4, 5, 6, 7, 8];
+        let with_amount = |amount: u64| {
+            let mut data = discriminator.to_vec();
+            data.extend(amount.to_le_bytes());
+            ix(vec![AccountMeta::new(account, false)], &data)
+        };
+        assert_eq!(
+            instruction_shape(&[with_amount(1)]),
+            instruction_shape(&[with_amount(2)])
+        );
+        assert_ne!(
+            instruction_shape(&[with_amount(1)]),
+            instruction_shape(&[ix(vec![AccountMeta::new_readonly(account, false)], &[0; 16])])
+        );
+        assert_ne!(
+            instruction_shape(&[with_amount(1)]),
+            instruction_shape(&[with_amount(1), with_amount(1)])
+        );
+    }
+    #[test]
+    fn test_with_margin() {
+        let rpc = Arc::new(RpcClient::new("http://127.0.0.1:8899".to_string()));
+        let estimator = ComputeUnitEstimator::new(rpc).with_margin_bps(1_000);
+        assert_eq!(estimator.with_margin(99_700), 110_000);
+        assert_eq!(estimator.with_margin(2_000_000), MAX_COMPUTE_UNITS);
+    }
+}
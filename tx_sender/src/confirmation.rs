@@ -0,0 +1,195 @@
+//! tracks sent transactions until they land, fail or expire
+
+use anyhow::{anyhow, Context, Result};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature,
+    transaction::TransactionError,
+};
+use solana_transaction_status::{
+    EncodedTransactionWithStatusMeta, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// a landed transaction is fetched up to this many times, doubling the delay between attempts
+const TRANSACTION_FETCH_ATTEMPTS: u32 = 5;
+const TRANSACTION_FETCH_DELAY: Duration = Duration::from_millis(500);
+
+/// the final state of a sent transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxOutcome {
+    /// the transaction executed successfully and reached the tracked commitment
+    Landed { slot: u64 },
+    /// the transaction was included in a block but returned an error
+    Failed { slot: u64, err: TransactionError },
+    /// the transaction's blockhash expired before it was seen on chain
+    Expired,
+    /// the transaction failed preflight simulation and was never broadcast
+    Rejected { err: TransactionError },
+    /// broadcasting stopped on shutdown or repeated rpc errors before the transaction's final
+    /// state was known, it may yet land
+    Pending,
+}
+
+impl TxOutcome {
+    pub fn is_landed(&self) -> bool {
+        matches!(self, Self::Landed { .. })
+    }
+}
+
+#[derive(Clone)]
+pub struct ConfirmationTracker {
+    rpc: Arc<RpcClient>,
+    commitment: CommitmentConfig,
+    poll_interval: Duration,
+}
+
+impl ConfirmationTracker {
+    pub fn new(rpc: Arc<RpcClient>, commitment: CommitmentConfig) -> Self {
+        Self {
+            rpc,
+            commitment,
+            poll_interval: Duration::from_millis(400),
+        }
+    }
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+    /// polls the signature status until the transaction reaches the tracked commitment, fails,
+    /// or the chain passes `last_valid_block_height` without it landing
+    pub async fn track(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+    ) -> Result<TxOutcome> {
+        let mut ticker = tokio::time::interval(self.poll_interval);
+        loop {
+            ticker.tick().await;
+            if let Some(outcome) = self.status(signature).await? {
+                return Ok(outcome);
+            }
+            let block_height = self
+                .rpc
+                .get_block_height_with_commitment(CommitmentConfig::confirmed())
+                .await?;
+            if block_height > last_valid_block_height {
+                // the transaction may have landed between the two requests
+                return Ok(self.status(signature).await?.unwrap_or(TxOutcome::Expired));
+            }
+        }
+    }
+    /// returns the outcome of the transaction if it has reached the tracked commitment
+    pub async fn status(&self, signature: &Signature) -> Result<Option<TxOutcome>> {
+        let statuses = self.rpc.get_signature_statuses(&[*signature]).await?.value;
+        let status = match statuses.into_iter().next().flatten() {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        if !status.satisfies_commitment(self.commitment) {
+            return Ok(None);
+        }
+        Ok(Some(match status.err {
+            Some(err) => TxOutcome::Failed {
+                slot: status.slot,
+                err,
+            },
+            None => TxOutcome::Landed { slot: status.slot },
+        }))
+    }
+    /// fetches a landed transaction along with its status meta
+    pub async fn transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<EncodedTransactionWithStatusMeta> {
+        // transaction lookups are not available at processed commitment
+        let commitment = if self.commitment == CommitmentConfig::processed() {
+            CommitmentConfig::confirmed()
+        } else {
+            self.commitment
+        };
+        let tx = self
+            .rpc
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(commitment),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+            .with_context(|| format!("failed to fetch transaction {signature}"))?;
+        Ok(tx.transaction)
+    }
+    /// fetches a landed transaction, backing off between attempts as it may not be
+    /// queryable the moment its status is
+    pub async fn landed_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<EncodedTransactionWithStatusMeta> {
+        let mut delay = TRANSACTION_FETCH_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.transaction(signature).await {
+                Ok(tx) => return Ok(tx),
+                Err(err) if attempt < TRANSACTION_FETCH_ATTEMPTS => {
+                    log::warn!("failed to fetch {signature} on attempt {attempt} {err:#?}, retrying...");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    /// returns the change in `owner`'s balance of `mint` caused by a landed transaction
+    pub async fn token_balance_change(
+        &self,
+        signature: &Signature,
+        owner: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<i128> {
+        let tx = self.transaction(signature).await?;
+        token_balance_change(&tx, owner, mint)
+            .with_context(|| format!("transaction {signature} has no status meta"))
+    }
+}
+
+/// returns the change in `owner`'s balance of `mint` recorded in a fetched transaction's meta
+pub fn token_balance_change(
+    tx: &EncodedTransactionWithStatusMeta,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<i128> {
+    let meta = tx
+        .meta
+        .as_ref()
+        .ok_or_else(|| anyhow!("transaction has no status meta"))?;
+    let pre_balances: Option<Vec<UiTransactionTokenBalance>> =
+        meta.pre_token_balances.clone().into();
+    let post_balances: Option<Vec<UiTransactionTokenBalance>> =
+        meta.post_token_balances.clone().into();
+    Ok(owner_balance(&post_balances.unwrap_or_default(), owner, mint) as i128
+        - owner_balance(&pre_balances.unwrap_or_default(), owner, mint) as i128)
+}
+
+/// sums the token balances of `mint` held by `owner`
+fn owner_balance(balances: &[UiTransactionTokenBalance], owner: &Pubkey, mint: &Pubkey) -> u64 {
+    let owner = owner.to_string();
+    let mint = mint.to_string();
+    balances
+        .iter()
+        .filter(|balance| {
+            balance.mint == mint && Option::<&String>::from(balance.owner.as_ref()) == Some(&owner)
+        })
+        .filter_map(|balance| balance.ui_token_amount.amount.parse::<u64>().ok())
+        .sum()
+}
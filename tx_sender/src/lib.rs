@@ -0,0 +1,8 @@
+//! shared helpers for sending transactions and tracking them until they land
+
+pub mod compute_units;
+pub mod confirmation;
+pub mod nonce;
+pub mod priority_fee;
+pub mod sender;
+pub mod submitter;
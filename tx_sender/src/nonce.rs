@@ -0,0 +1,72 @@
+//! durable nonce accounts, which let transactions be signed ahead of time and stay valid
+//! until the nonce is advanced instead of expiring with a recent blockhash
+
+use anyhow::{anyhow, Result};
+use solana_client::{nonblocking::rpc_client::RpcClient, nonce_utils};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, hash::Hash, instruction::Instruction, pubkey::Pubkey,
+    system_instruction, system_program,
+};
+
+/// seed nonce accounts are derived from their authority with unless another is given
+pub const DEFAULT_NONCE_SEED: &str = "auto-jlp-deposit";
+
+/// the stored nonce of a durable nonce account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DurableNonce {
+    pub account: Pubkey,
+    pub authority: Pubkey,
+    /// used in place of a recent blockhash by transactions signed against the nonce
+    pub blockhash: Hash,
+}
+
+impl DurableNonce {
+    pub async fn fetch(
+        rpc: &RpcClient,
+        account: Pubkey,
+        commitment: CommitmentConfig,
+    ) -> Result<Self> {
+        let nonce_account = nonce_utils::nonblocking::get_account_with_commitment(rpc, &account, commitment)
+            .await
+            .map_err(|err| anyhow!("failed to fetch nonce account {account} {err}"))?;
+        let data = nonce_utils::nonblocking::data_from_account(&nonce_account)
+            .map_err(|err| anyhow!("invalid nonce account {account} {err}"))?;
+        Ok(Self {
+            account,
+            authority: data.authority,
+            blockhash: data.blockhash(),
+        })
+    }
+    /// advances the nonce, which must be the first instruction of a durable transaction
+    pub fn advance_ix(&self) -> Instruction {
+        system_instruction::advance_nonce_account(&self.account, &self.authority)
+    }
+}
+
+/// returns the nonce account `authority` creates from `seed`, which keeps one nonce account
+/// per wallet without storing another keypair
+pub fn nonce_address(authority: &Pubkey, seed: &str) -> Result<Pubkey> {
+    Ok(Pubkey::create_with_seed(authority, seed, &system_program::id())?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_nonce_address() {
+        let authority = Pubkey::new_unique();
+        let account = nonce_address(&authority, DEFAULT_NONCE_SEED).unwrap();
+        assert_eq!(account, nonce_address(&authority, DEFAULT_NONCE_SEED).unwrap());
+        assert_ne!(account, nonce_address(&authority, "other").unwrap());
+        assert_ne!(account, nonce_address(&Pubkey::new_unique(), DEFAULT_NONCE_SEED).unwrap());
+        let nonce = DurableNonce {
+            account,
+            authority,
+            blockhash: Hash::new_unique(),
+        };
+        let ix = nonce.advance_ix();
+        assert_eq!(ix.program_id, system_program::id());
+        assert_eq!(ix.accounts[0].pubkey, account);
+        assert!(ix.accounts.iter().any(|meta| meta.pubkey == authority && meta.is_signer));
+    }
+}
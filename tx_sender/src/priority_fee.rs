@@ -0,0 +1,151 @@
+//! priority fees estimated from the fees recently paid to write lock the accounts a
+//! transaction touches, escalated each time the transaction is re-signed
+
+use anyhow::{Context, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use std::sync::Arc;
+
+/// getRecentPrioritizationFees accepts at most this many accounts
+const MAX_FEE_ACCOUNTS: usize = 128;
+const BPS_POWER: u128 = 10_000;
+
+/// estimates a compute unit price, in micro-lamports, from a percentile of the fees paid in
+/// recent slots, clamped between a floor and a ceiling
+#[derive(Clone)]
+pub struct PriorityFeeEstimator {
+    rpc: Arc<RpcClient>,
+    percentile: u8,
+    floor: u64,
+    ceiling: u64,
+    escalation_bps: u64,
+}
+
+impl PriorityFeeEstimator {
+    pub fn new(rpc: Arc<RpcClient>) -> Self {
+        Self {
+            rpc,
+            percentile: 75,
+            floor: 10_000,
+            ceiling: 1_000_000,
+            escalation_bps: 5_000,
+        }
+    }
+    /// percentile (0-100) of the recent per slot fees to pay
+    pub fn with_percentile(mut self, percentile: u8) -> Self {
+        self.percentile = percentile.min(100);
+        self
+    }
+    /// lowest compute unit price paid, in micro-lamports
+    pub fn with_floor(mut self, floor: u64) -> Self {
+        self.floor = floor;
+        self
+    }
+    /// highest compute unit price paid, in micro-lamports, including escalations
+    pub fn with_ceiling(mut self, ceiling: u64) -> Self {
+        self.ceiling = ceiling;
+        self
+    }
+    /// increase in bps applied to the fee each time the transaction is re-signed
+    pub fn with_escalation_bps(mut self, escalation_bps: u64) -> Self {
+        self.escalation_bps = escalation_bps;
+        self
+    }
+    /// returns the compute unit price for a transaction write locking `writable_accounts`.
+    /// the rpc reports, for each recent slot, the highest of the minimum fees paid to
+    /// lock any one of the accounts
+    pub async fn estimate(&self, writable_accounts: &[Pubkey]) -> Result<u64> {
+        let accounts = &writable_accounts[..writable_accounts.len().min(MAX_FEE_ACCOUNTS)];
+        let fees = self
+            .rpc
+            .get_recent_prioritization_fees(accounts)
+            .await
+            .with_context(|| "failed to fetch recent prioritization fees")?
+            .into_iter()
+            .map(|fee| fee.prioritization_fee)
+            .collect();
+        Ok(self.clamp(percentile_fee(fees, self.percentile)))
+    }
+    /// returns the fee for the next signing, at least the escalated `previous` fee
+    /// and at least `estimate`
+    pub fn escalate(&self, previous: u64, estimate: u64) -> u64 {
+        let escalated = previous as u128 * (BPS_POWER + self.escalation_bps as u128) / BPS_POWER;
+        self.clamp(u64::try_from(escalated).unwrap_or(u64::MAX).max(estimate))
+    }
+    pub fn floor(&self) -> u64 {
+        self.floor
+    }
+    fn clamp(&self, fee: u64) -> u64 {
+        fee.max(self.floor).min(self.ceiling.max(self.floor))
+    }
+}
+
+/// returns the writable accounts of `instructions` that aren't signers, which are the
+/// accounts whose lock the transaction competes for
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = vec![];
+    for account in instructions.iter().flat_map(|ix| ix.accounts.iter()) {
+        if account.is_writable && !account.is_signer && !accounts.contains(&account.pubkey) {
+            accounts.push(account.pubkey);
+        }
+    }
+    accounts
+}
+
+/// returns the fee at `percentile` (0-100) of `fees`, zero when no fees were reported
+fn percentile_fee(mut fees: Vec<u64>, percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let index = (fees.len() - 1) * percentile.min(100) as usize / 100;
+    fees[index]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+    #[test]
+    fn test_percentile_fee() {
+        assert_eq!(percentile_fee(vec![], 75), 0);
+        let fees = vec![500, 0, 100, 400, 200, 300];
+        assert_eq!(percentile_fee(fees.clone(), 0), 0);
+        assert_eq!(percentile_fee(fees.clone(), 50), 200);
+        assert_eq!(percentile_fee(fees.clone(), 75), 300);
+        assert_eq!(percentile_fee(fees, 100), 500);
+    }
+    #[test]
+    fn test_escalate() {
+        let rpc = Arc::new(RpcClient::new("http://127.0.0.1:8899".to_string()));
+        let estimator = PriorityFeeEstimator::new(rpc)
+            .with_floor(1_000)
+            .with_ceiling(10_000)
+            .with_escalation_bps(5_000);
+        assert_eq!(estimator.clamp(0), 1_000);
+        assert_eq!(estimator.clamp(20_000), 10_000);
+        assert_eq!(estimator.escalate(2_000, 0), 3_000);
+        // a fresh estimate above the escalated fee wins
+        assert_eq!(estimator.escalate(2_000, 5_000), 5_000);
+        assert_eq!(estimator.escalate(8_000, 0), 10_000);
+    }
+    #[test]
+    fn test_writable_accounts() {
+        let (signer, pool, custody, program) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let ix = |accounts| Instruction::new_with_bytes(program, &[], accounts);
+        let ixs = [
+            ix(vec![
+                AccountMeta::new(signer, true),
+                AccountMeta::new(pool, false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ]),
+            ix(vec![AccountMeta::new(pool, false), AccountMeta::new(custody, false)]),
+        ];
+        assert_eq!(writable_accounts(&ixs), vec![pool, custody]);
+    }
+}
@@ -0,0 +1,341 @@
+//! sends transactions until they land, rebroadcasting on an interval and re-signing
+//! with a fresh blockhash whenever the previous one expires.
+//!
+//! the priority fee is only escalated when re-signing. a transaction re-signed with a higher
+//! fee while the previous signature is still valid would be a second transaction, and both
+//! could land, so the fee is never raised before the previous blockhash has expired
+
+use crate::confirmation::{ConfirmationTracker, TxOutcome};
+use crate::nonce::DurableNonce;
+use crate::priority_fee::PriorityFeeEstimator;
+use crate::submitter::{RpcSubmitter, Submitter};
+use anyhow::{anyhow, Result};
+use solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, hash::Hash, instruction::Instruction, pubkey::Pubkey,
+    signature::Signature,
+    transaction::{TransactionError, VersionedTransaction},
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// consecutive rpc errors tolerated while tracking a broadcast transaction before it is
+/// returned as pending
+const MAX_RPC_ERRORS: usize = 30;
+
+/// passed to the transaction builder each time the transaction needs to be (re)signed
+#[derive(Debug, Clone, Copy)]
+pub struct Attempt {
+    pub blockhash: Hash,
+    pub last_valid_block_height: u64,
+    /// zero for the first signing, incremented on every re-sign
+    pub number: usize,
+    /// compute unit price in micro-lamports, zero unless a priority fee estimator is set
+    pub priority_fee: u64,
+}
+
+/// the last signature sent along with its final state
+#[derive(Debug, Clone)]
+pub struct Sent {
+    pub signature: Signature,
+    pub outcome: TxOutcome,
+    /// number of times the transaction was signed
+    pub attempts: usize,
+}
+
+/// what invalidates a broadcast transaction that hasn't landed
+#[derive(Debug, Clone, Copy)]
+enum Expiry {
+    /// the recent blockhash it was signed with is too old past this block height
+    BlockHeight(u64),
+    /// the durable nonce it was signed with has been advanced, or it has been broadcast
+    /// until the deadline
+    Nonce(DurableNonce, tokio::time::Instant),
+}
+
+#[derive(Clone)]
+pub struct TransactionSender {
+    rpc: Arc<RpcClient>,
+    tracker: ConfirmationTracker,
+    submitter: Arc<dyn Submitter>,
+    rebroadcast_interval: Duration,
+    max_resigns: usize,
+    max_durable_broadcast: Duration,
+    skip_preflight: bool,
+    /// estimator along with the writable accounts fees are estimated for
+    priority_fee: Option<(PriorityFeeEstimator, Vec<Pubkey>)>,
+    shutdown: Option<watch::Receiver<bool>>,
+}
+
+impl TransactionSender {
+    pub fn new(rpc: Arc<RpcClient>, commitment: CommitmentConfig) -> Self {
+        Self {
+            tracker: ConfirmationTracker::new(rpc.clone(), commitment),
+            submitter: Arc::new(RpcSubmitter::new(rpc.clone())),
+            rpc,
+            rebroadcast_interval: Duration::from_secs(2),
+            max_resigns: 3,
+            max_durable_broadcast: Duration::from_secs(90),
+            skip_preflight: false,
+            priority_fee: None,
+            shutdown: None,
+        }
+    }
+    pub fn with_rebroadcast_interval(mut self, rebroadcast_interval: Duration) -> Self {
+        self.rebroadcast_interval = rebroadcast_interval;
+        self
+    }
+    /// maximum number of times the transaction is re-signed after its blockhash expires
+    pub fn with_max_resigns(mut self, max_resigns: usize) -> Self {
+        self.max_resigns = max_resigns;
+        self
+    }
+    /// how long a durable transaction is broadcast before it is given up on as expired, as
+    /// nothing else expires it while the nonce isn't advanced
+    pub fn with_max_durable_broadcast(mut self, max_durable_broadcast: Duration) -> Self {
+        self.max_durable_broadcast = max_durable_broadcast;
+        self
+    }
+    pub fn with_skip_preflight(mut self, skip_preflight: bool) -> Self {
+        self.skip_preflight = skip_preflight;
+        self
+    }
+    /// estimates the priority fee passed to the builder from the fees recently paid to
+    /// write lock `writable_accounts`, escalating it on every re-sign
+    pub fn with_priority_fee(
+        mut self,
+        estimator: PriorityFeeEstimator,
+        writable_accounts: Vec<Pubkey>,
+    ) -> Self {
+        self.priority_fee = Some((estimator, writable_accounts));
+        self
+    }
+    /// submits through `submitter` instead of the rpc client's sendTransaction
+    pub fn with_submitter(mut self, submitter: Arc<dyn Submitter>) -> Self {
+        self.submitter = submitter;
+        self
+    }
+    /// stops broadcasting once `shutdown` is set, returning the in-flight signature as pending
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+    /// instructions the builder must append to transactions sent through the submitter
+    pub fn tip_instructions(&self, payer: &Pubkey) -> Vec<Instruction> {
+        self.submitter.tip_instructions(payer)
+    }
+    pub fn tracker(&self) -> &ConfirmationTracker {
+        &self.tracker
+    }
+    /// signs the transaction returned by `build` and broadcasts it until it lands, fails, is
+    /// rejected by preflight or shutdown is requested. when the blockhash expires without the
+    /// transaction being seen, `build` is called again with a fresh blockhash, up to
+    /// `max_resigns` times.
+    pub async fn send<F>(&self, mut build: F) -> Result<Sent>
+    where
+        F: FnMut(&Attempt) -> Result<VersionedTransaction>,
+    {
+        let mut last_signature = None;
+        let mut priority_fee = 0;
+        for number in 0..=self.max_resigns {
+            let (blockhash, last_valid_block_height) = self
+                .rpc
+                .get_latest_blockhash_with_commitment(self.rpc.commitment())
+                .await?;
+            if let Some((estimator, writable_accounts)) = &self.priority_fee {
+                let estimate = match estimator.estimate(writable_accounts).await {
+                    Ok(estimate) => estimate,
+                    Err(err) => {
+                        log::warn!("failed to estimate priority fee {err:#?}");
+                        estimator.floor()
+                    }
+                };
+                priority_fee = if number == 0 {
+                    estimate
+                } else {
+                    estimator.escalate(priority_fee, estimate)
+                };
+            }
+            let attempt = Attempt {
+                blockhash,
+                last_valid_block_height,
+                number,
+                priority_fee,
+            };
+            let tx = build(&attempt)?;
+            let signature = *tx
+                .signatures
+                .first()
+                .ok_or_else(|| anyhow!("transaction is not signed"))?;
+            if number > 0 {
+                log::warn!(
+                    "re-signed expired transaction as {signature}, attempt {}, priority fee {priority_fee}",
+                    number + 1
+                );
+            }
+            last_signature = Some(signature);
+            if let Some(outcome) = self
+                .broadcast(&tx, &signature, Expiry::BlockHeight(last_valid_block_height))
+                .await?
+            {
+                return Ok(Sent {
+                    signature,
+                    outcome,
+                    attempts: number + 1,
+                });
+            }
+        }
+        Ok(Sent {
+            signature: last_signature.ok_or_else(|| anyhow!("transaction was never signed"))?,
+            outcome: TxOutcome::Expired,
+            attempts: self.max_resigns + 1,
+        })
+    }
+    /// broadcasts a transaction signed ahead of time against `nonce` until it lands, fails, the
+    /// nonce is advanced by something else or `max_durable_broadcast` passes. it is never
+    /// re-signed, so there is no escalation. an expired transaction stays valid until the
+    /// nonce is advanced, which is left to the caller as only the nonce authority can
+    pub async fn send_durable(&self, tx: &VersionedTransaction, nonce: DurableNonce) -> Result<Sent> {
+        let signature = *tx
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("transaction is not signed"))?;
+        let deadline = tokio::time::Instant::now() + self.max_durable_broadcast;
+        let outcome = self
+            .broadcast(tx, &signature, Expiry::Nonce(nonce, deadline))
+            .await?
+            .unwrap_or(TxOutcome::Expired);
+        Ok(Sent {
+            signature,
+            outcome,
+            attempts: 1,
+        })
+    }
+    /// rebroadcasts a signed transaction until it reaches a final state, returning None
+    /// once it has expired and pending if its state can't be tracked
+    async fn broadcast(
+        &self,
+        tx: &VersionedTransaction,
+        signature: &Signature,
+        expiry: Expiry,
+    ) -> Result<Option<TxOutcome>> {
+        // preflight only the first broadcast, a rebroadcast of a landed transaction would fail it
+        if let Err(err) = self.send_once(tx, self.skip_preflight).await {
+            if let Some(err) = terminal_error(&err) {
+                return Ok(Some(TxOutcome::Rejected { err }));
+            }
+            log::warn!("failed to send {signature} {err:#?}");
+        }
+        let mut last_broadcast = tokio::time::Instant::now();
+        let mut ticker = tokio::time::interval(self.tracker.poll_interval());
+        let mut shutdown = self.shutdown.clone();
+        let mut rpc_errors = 0;
+        loop {
+            match &mut shutdown {
+                Some(shutdown) => {
+                    tokio::select! {
+                        _ = ticker.tick() => {}
+                        Ok(()) = shutdown.changed() => {}
+                    }
+                    if *shutdown.borrow() {
+                        return Ok(Some(TxOutcome::Pending));
+                    }
+                }
+                None => {
+                    ticker.tick().await;
+                }
+            }
+            // the transaction is already out, so rpc errors are retried rather than returned
+            if rpc_errors >= MAX_RPC_ERRORS {
+                log::error!("{rpc_errors} rpc errors in a row tracking {signature}, giving up on it");
+                return Ok(Some(TxOutcome::Pending));
+            }
+            match self.tracker.status(signature).await {
+                Ok(Some(outcome)) => return Ok(Some(outcome)),
+                Ok(None) => rpc_errors = 0,
+                Err(err) => {
+                    log::warn!("failed to fetch the status of {signature} {err:#?}, retrying...");
+                    rpc_errors += 1;
+                    continue;
+                }
+            }
+            if last_broadcast.elapsed() < self.rebroadcast_interval {
+                continue;
+            }
+            match self.expired(expiry).await {
+                Ok(false) => {}
+                // the transaction may have landed between the two requests
+                Ok(true) => match self.tracker.status(signature).await {
+                    Ok(outcome) => return Ok(outcome),
+                    Err(err) => {
+                        log::warn!("failed to fetch the status of {signature} {err:#?}, retrying...");
+                        rpc_errors += 1;
+                        continue;
+                    }
+                },
+                Err(err) => {
+                    log::warn!("failed to check if {signature} expired {err:#?}, retrying...");
+                    rpc_errors += 1;
+                    continue;
+                }
+            }
+            if let Err(err) = self.send_once(tx, true).await {
+                log::warn!("failed to rebroadcast {signature} {err:#?}");
+            }
+            last_broadcast = tokio::time::Instant::now();
+        }
+    }
+    async fn expired(&self, expiry: Expiry) -> Result<bool> {
+        Ok(match expiry {
+            Expiry::BlockHeight(last_valid_block_height) => {
+                self.rpc
+                    .get_block_height_with_commitment(CommitmentConfig::confirmed())
+                    .await?
+                    > last_valid_block_height
+            }
+            Expiry::Nonce(_, deadline) if tokio::time::Instant::now() >= deadline => true,
+            Expiry::Nonce(nonce, _) => {
+                DurableNonce::fetch(&self.rpc, nonce.account, CommitmentConfig::confirmed())
+                    .await?
+                    .blockhash
+                    != nonce.blockhash
+            }
+        })
+    }
+    async fn send_once(
+        &self,
+        tx: &VersionedTransaction,
+        skip_preflight: bool,
+    ) -> std::result::Result<Signature, ClientError> {
+        self.submitter.submit(tx, skip_preflight).await
+    }
+}
+
+/// returns the transaction error if a send failed in a way that resending can't fix
+fn terminal_error(err: &ClientError) -> Option<TransactionError> {
+    match err.get_transaction_error()? {
+        TransactionError::BlockhashNotFound | TransactionError::AlreadyProcessed => None,
+        err => Some(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_client::client_error::ClientErrorKind;
+    #[test]
+    fn test_terminal_error() {
+        let err = |tx_err| ClientError::from(ClientErrorKind::TransactionError(tx_err));
+        assert_eq!(terminal_error(&err(TransactionError::BlockhashNotFound)), None);
+        assert_eq!(terminal_error(&err(TransactionError::AlreadyProcessed)), None);
+        assert_eq!(
+            terminal_error(&err(TransactionError::InsufficientFundsForFee)),
+            Some(TransactionError::InsufficientFundsForFee)
+        );
+        assert_eq!(
+            terminal_error(&ClientError::from(ClientErrorKind::Custom("timeout".to_string()))),
+            None
+        );
+    }
+}
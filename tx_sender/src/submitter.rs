@@ -0,0 +1,457 @@
+//! backends that submit signed transactions to the cluster. confirmation is always tracked
+//! through the rpc client, only the submission path differs
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    connection_cache::ConnectionCache,
+    nonblocking::{rpc_client::RpcClient, tpu_client::TpuClient},
+    rpc_config::RpcSendTransactionConfig,
+    rpc_request::{RpcError, RpcResponseErrorData},
+    tpu_client::{TpuClientConfig, MAX_FANOUT_SLOTS},
+};
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_sdk::{
+    instruction::Instruction, pubkey::Pubkey, signature::Signature, system_instruction,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::UiTransactionEncoding;
+use solana_udp_client::{UdpConfig, UdpConnectionManager, UdpPool};
+use std::sync::Arc;
+
+#[async_trait::async_trait]
+pub trait Submitter: Send + Sync {
+    /// submits a signed transaction once, returning its signature. errors keep the rpc error
+    /// kind so the sender can tell terminal preflight failures from transient ones
+    async fn submit(
+        &self,
+        tx: &VersionedTransaction,
+        skip_preflight: bool,
+    ) -> std::result::Result<Signature, ClientError>;
+    /// instructions the builder appends to every transaction sent through this backend
+    fn tip_instructions(&self, _payer: &Pubkey) -> Vec<Instruction> {
+        vec![]
+    }
+}
+
+/// submits through a single rpc node's sendTransaction
+pub struct RpcSubmitter {
+    rpc: Arc<RpcClient>,
+}
+
+impl RpcSubmitter {
+    pub fn new(rpc: Arc<RpcClient>) -> Self {
+        Self { rpc }
+    }
+}
+
+#[async_trait::async_trait]
+impl Submitter for RpcSubmitter {
+    async fn submit(
+        &self,
+        tx: &VersionedTransaction,
+        skip_preflight: bool,
+    ) -> std::result::Result<Signature, ClientError> {
+        self.rpc
+            .send_transaction_with_config(
+                tx,
+                RpcSendTransactionConfig {
+                    skip_preflight,
+                    preflight_commitment: Some(self.rpc.commitment().commitment),
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    // rebroadcasting is handled by the sender rather than by the rpc node
+                    max_retries: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+    }
+}
+
+/// submits through several backends at once, returning as soon as any of them accepts the
+/// transaction while the remaining sends carry on in the background
+pub struct FanOutSubmitter {
+    submitters: Vec<Arc<dyn Submitter>>,
+}
+
+impl FanOutSubmitter {
+    /// fans out to the sendTransaction of every one of `rpcs`
+    pub fn new(rpcs: Vec<Arc<RpcClient>>) -> Self {
+        Self::from_submitters(
+            rpcs.into_iter()
+                .map(|rpc| Arc::new(RpcSubmitter::new(rpc)) as Arc<dyn Submitter>)
+                .collect(),
+        )
+    }
+    /// tip instructions aren't collected from `submitters`, so they shouldn't require any
+    pub fn from_submitters(submitters: Vec<Arc<dyn Submitter>>) -> Self {
+        Self { submitters }
+    }
+}
+
+#[async_trait::async_trait]
+impl Submitter for FanOutSubmitter {
+    async fn submit(
+        &self,
+        tx: &VersionedTransaction,
+        skip_preflight: bool,
+    ) -> std::result::Result<Signature, ClientError> {
+        let (result_tx, mut result_rx) = tokio::sync::mpsc::channel(self.submitters.len().max(1));
+        for submitter in &self.submitters {
+            let (submitter, tx, result_tx) = (submitter.clone(), tx.clone(), result_tx.clone());
+            tokio::task::spawn(async move {
+                let _ = result_tx.send(submitter.submit(&tx, skip_preflight).await).await;
+            });
+        }
+        drop(result_tx);
+        let mut first_err = None;
+        while let Some(result) = result_rx.recv().await {
+            match result {
+                Ok(signature) => return Ok(signature),
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        Err(first_err.unwrap_or_else(|| {
+            ClientErrorKind::Custom("no backends to fan out to".to_string()).into()
+        }))
+    }
+}
+
+/// submits single transaction bundles to a block engine's sendBundle endpoint. the block
+/// engine only accepts bundles that tip, so a tip transfer is appended to every transaction
+pub struct BundleSubmitter {
+    client: reqwest::Client,
+    url: String,
+    tip_account: Pubkey,
+    tip_lamports: u64,
+}
+
+impl BundleSubmitter {
+    pub fn new(url: String, tip_account: Pubkey, tip_lamports: u64) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().build()?,
+            url,
+            tip_account,
+            tip_lamports,
+        })
+    }
+    async fn send_bundle(&self, tx: &VersionedTransaction) -> Result<String> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(bincode::serialize(tx)?);
+        let response: serde_json::Value = self
+            .client
+            .post(&self.url)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sendBundle",
+                "params": [[encoded], {"encoding": "base64"}],
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(err) = response.get("error") {
+            return Err(anyhow!(
+                "block engine rejected bundle, code {}, {}",
+                err["code"],
+                err["message"].as_str().unwrap_or_default()
+            ));
+        }
+        response["result"]
+            .as_str()
+            .map(|bundle_id| bundle_id.to_string())
+            .ok_or_else(|| anyhow!("block engine returned no bundle id {response}"))
+    }
+}
+
+#[async_trait::async_trait]
+impl Submitter for BundleSubmitter {
+    /// block engines simulate bundles themselves, so preflight is never requested
+    async fn submit(
+        &self,
+        tx: &VersionedTransaction,
+        _skip_preflight: bool,
+    ) -> std::result::Result<Signature, ClientError> {
+        let signature = *tx.signatures.first().ok_or_else(|| {
+            ClientError::from(ClientErrorKind::Custom("transaction is not signed".to_string()))
+        })?;
+        match self.send_bundle(tx).await {
+            Ok(bundle_id) => {
+                log::debug!("sent {signature} in bundle {bundle_id}");
+                Ok(signature)
+            }
+            Err(err) => Err(RpcError::RpcResponseError {
+                code: 0,
+                message: format!("{err:#}"),
+                data: RpcResponseErrorData::Empty,
+            }
+            .into()),
+        }
+    }
+    fn tip_instructions(&self, payer: &Pubkey) -> Vec<Instruction> {
+        vec![system_instruction::transfer(payer, &self.tip_account, self.tip_lamports)]
+    }
+}
+
+/// connections kept open to each leader
+const TPU_CONNECTION_POOL_SIZE: usize = 4;
+
+enum LeaderClient {
+    Quic(TpuClient<QuicPool, QuicConnectionManager, QuicConfig>),
+    Udp(TpuClient<UdpPool, UdpConnectionManager, UdpConfig>),
+}
+
+/// sends directly to the tpu ports of the current and upcoming leaders, skipping the rpc
+/// node's forwarding queue. leaders are tracked from the rpc's leader schedule and slot
+/// subscription. the tpu never runs preflight, so program errors only surface on confirmation
+pub struct TpuSubmitter {
+    client: LeaderClient,
+}
+
+impl TpuSubmitter {
+    /// sends to the leaders of the next `fanout_slots` slots (1-100), over quic unless
+    /// `use_quic` is false, in which case udp is used
+    pub async fn new(
+        rpc: Arc<RpcClient>,
+        ws_url: &str,
+        fanout_slots: u64,
+        use_quic: bool,
+    ) -> Result<Self> {
+        let config = TpuClientConfig {
+            fanout_slots: fanout_slots.clamp(1, MAX_FANOUT_SLOTS),
+        };
+        let connection_cache = if use_quic {
+            ConnectionCache::new_quic("auto-jlp-tpu", TPU_CONNECTION_POOL_SIZE)
+        } else {
+            ConnectionCache::with_udp("auto-jlp-tpu", TPU_CONNECTION_POOL_SIZE)
+        };
+        let client = match connection_cache {
+            ConnectionCache::Quic(cache) => LeaderClient::Quic(
+                TpuClient::new_with_connection_cache(rpc, ws_url, config, cache).await?,
+            ),
+            ConnectionCache::Udp(cache) => LeaderClient::Udp(
+                TpuClient::new_with_connection_cache(rpc, ws_url, config, cache).await?,
+            ),
+        };
+        Ok(Self { client })
+    }
+}
+
+#[async_trait::async_trait]
+impl Submitter for TpuSubmitter {
+    async fn submit(
+        &self,
+        tx: &VersionedTransaction,
+        _skip_preflight: bool,
+    ) -> std::result::Result<Signature, ClientError> {
+        let signature = *tx.signatures.first().ok_or_else(|| {
+            ClientError::from(ClientErrorKind::Custom("transaction is not signed".to_string()))
+        })?;
+        let wire_tx = bincode::serialize(tx)
+            .map_err(|err| ClientError::from(ClientErrorKind::Custom(err.to_string())))?;
+        // fails only when every leader in the fanout was unreachable
+        match &self.client {
+            LeaderClient::Quic(client) => client.try_send_wire_transaction(wire_tx).await?,
+            LeaderClient::Udp(client) => client.try_send_wire_transaction(wire_tx).await?,
+        }
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::{
+        hash::Hash, message::VersionedMessage, signature::Keypair, signer::Signer,
+        transaction::Transaction,
+    };
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// serves json rpc requests over http on a local port, answering each with `respond`
+    /// and keeping every request body it received
+    async fn mock_server(
+        respond: fn(&serde_json::Value) -> serde_json::Value,
+    ) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        tokio::task::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let received = received.clone();
+                tokio::task::spawn(async move {
+                    let mut buf = vec![];
+                    let mut chunk = [0_u8; 4096];
+                    loop {
+                        // parse every complete request buffered so far
+                        while let Some(header_end) =
+                            buf.windows(4).position(|window| window == b"\r\n\r\n")
+                        {
+                            let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+                            let content_length = headers
+                                .lines()
+                                .find_map(|line| line.strip_prefix("content-length:"))
+                                .and_then(|len| len.trim().parse::<usize>().ok())
+                                .unwrap_or_default();
+                            let body_end = header_end + 4 + content_length;
+                            if buf.len() < body_end {
+                                break;
+                            }
+                            let request: serde_json::Value =
+                                serde_json::from_slice(&buf[header_end + 4..body_end]).unwrap();
+                            buf.drain(..body_end);
+                            let mut response = respond(&request);
+                            response["jsonrpc"] = "2.0".into();
+                            response["id"] = request["id"].clone();
+                            received.lock().unwrap().push(request);
+                            let body = response.to_string();
+                            let http = format!(
+                                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                                body.len()
+                            );
+                            stream.write_all(http.as_bytes()).await.unwrap();
+                        }
+                        match stream.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => buf.extend_from_slice(&chunk[..read]),
+                        }
+                    }
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    fn signed_tx() -> VersionedTransaction {
+        let payer = Keypair::new();
+        let ix = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+        let tx = Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &[&payer], Hash::default());
+        VersionedTransaction::from(tx)
+    }
+
+    fn respond_with_signature(request: &serde_json::Value) -> serde_json::Value {
+        match request["method"].as_str().unwrap() {
+            "getVersion" => serde_json::json!({"result": {"solana-core": "1.17.26", "feature-set": 0}}),
+            "sendTransaction" => {
+                let encoded = request["params"][0].as_str().unwrap();
+                let tx: VersionedTransaction = bincode::deserialize(
+                    &base64::engine::general_purpose::STANDARD.decode(encoded).unwrap(),
+                )
+                .unwrap();
+                serde_json::json!({"result": tx.signatures[0].to_string()})
+            }
+            method => panic!("unexpected method {method}"),
+        }
+    }
+
+    fn respond_with_error(request: &serde_json::Value) -> serde_json::Value {
+        match request["method"].as_str().unwrap() {
+            "getVersion" => serde_json::json!({"result": {"solana-core": "1.17.26", "feature-set": 0}}),
+            _ => serde_json::json!({"error": {"code": -32005, "message": "node is behind"}}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_submitter() {
+        let (ok_url, ok_requests) = mock_server(respond_with_signature).await;
+        let (err_url, err_requests) = mock_server(respond_with_error).await;
+        let submitter = FanOutSubmitter::new(vec![
+            Arc::new(RpcClient::new(err_url.clone())),
+            Arc::new(RpcClient::new(ok_url)),
+        ]);
+        let tx = signed_tx();
+        assert_eq!(submitter.submit(&tx, true).await.unwrap(), tx.signatures[0]);
+        // the failing node is still sent the transaction, even if the other answered first
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let sent = |requests: &Mutex<Vec<serde_json::Value>>| {
+            requests
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|request| request["method"] == "sendTransaction")
+                .count()
+        };
+        assert_eq!(sent(&ok_requests), 1);
+        assert_eq!(sent(&err_requests), 1);
+
+        let failing = FanOutSubmitter::new(vec![Arc::new(RpcClient::new(err_url))]);
+        assert!(failing.submit(&tx, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_submitter() {
+        let (url, requests) = mock_server(|request| match request["method"].as_str().unwrap() {
+            "sendBundle" => serde_json::json!({"result": "bundle-id"}),
+            method => panic!("unexpected method {method}"),
+        })
+        .await;
+        let (tip_account, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let submitter = BundleSubmitter::new(url, tip_account, 10_000).unwrap();
+        let tip_ixs = submitter.tip_instructions(&payer);
+        assert_eq!(tip_ixs, vec![system_instruction::transfer(&payer, &tip_account, 10_000)]);
+
+        let tx = signed_tx();
+        assert_eq!(submitter.submit(&tx, false).await.unwrap(), tx.signatures[0]);
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(request["params"][1]["encoding"], "base64");
+        let bundled: VersionedTransaction = bincode::deserialize(
+            &base64::engine::general_purpose::STANDARD
+                .decode(request["params"][0][0].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(bundled.signatures, tx.signatures);
+        assert!(matches!(bundled.message, VersionedMessage::Legacy(_)));
+
+        let (url, _) = mock_server(|_| {
+            serde_json::json!({"error": {"code": -32602, "message": "bundle must tip"}})
+        })
+        .await;
+        let submitter = BundleSubmitter::new(url, tip_account, 0).unwrap();
+        let err = submitter.submit(&tx, false).await.unwrap_err();
+        assert!(err.to_string().contains("bundle must tip"));
+    }
+
+    /// requires a local solana-test-validator, run with `cargo test -p tx_sender -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_tpu_submitter() {
+        use solana_sdk::commitment_config::CommitmentConfig;
+        let rpc = Arc::new(RpcClient::new_with_commitment(
+            "http://127.0.0.1:8899".to_string(),
+            CommitmentConfig::confirmed(),
+        ));
+        let payer = Keypair::new();
+        let airdrop = rpc.request_airdrop(&payer.pubkey(), 1_000_000_000).await.unwrap();
+        while !rpc.confirm_transaction(&airdrop).await.unwrap() {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+        for use_quic in [true, false] {
+            let submitter = TpuSubmitter::new(rpc.clone(), "ws://127.0.0.1:8900", 4, use_quic)
+                .await
+                .unwrap();
+            let ix = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1_000_000);
+            let tx = Transaction::new_signed_with_payer(
+                &[ix],
+                Some(&payer.pubkey()),
+                &[&payer],
+                rpc.get_latest_blockhash().await.unwrap(),
+            );
+            let signature = submitter.submit(&tx.into(), true).await.unwrap();
+            let mut confirmed = false;
+            for _ in 0..50 {
+                if rpc.confirm_transaction(&signature).await.unwrap() {
+                    confirmed = true;
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            }
+            assert!(confirmed, "{signature} sent with use_quic {use_quic} did not land");
+        }
+    }
+}